@@ -0,0 +1,104 @@
+//! Computer players. Both engines search on a `BitBoard` copy of the game.
+
+use crate::{
+    bitboard::{BitBoard, BitSet},
+    Action, BoardBackend, Game, Player, Res,
+};
+
+pub mod mcts;
+pub mod minimax;
+
+use mcts::{Budget, Mcts};
+use minimax::Minimax;
+
+/// A search that can run on any board backend.
+trait Search {
+    fn search<B: BoardBackend>(&mut self, game: Game<B>) -> Res<Action>;
+}
+
+/// Runs the search on the smallest bitboard the position fits into.
+fn search_on_bitboard<S: Search>(search: &mut S, game: &Game) -> Res<Action> {
+    let bits = BitBoard::<u64>::required_bits(game.board.width, game.board.height);
+    if bits <= 64 {
+        search.search(game.to_backend::<BitBoard<u64>>())
+    } else if bits <= 128 {
+        search.search(game.to_backend::<BitBoard<u128>>())
+    } else {
+        search.search(game.to_backend::<BitBoard<BitSet>>())
+    }
+}
+
+/// Columns that still have room for a piece, ordered from the center outwards. On a 3D board
+/// the center is the one of the layer.
+fn legal_columns<B: BoardBackend>(game: &Game<B>) -> Vec<usize> {
+    let mut columns: Vec<usize> = (0..game.board.width())
+        .filter(|&x| game.landing_row(x).is_some())
+        .collect();
+    columns.sort_by_key(|&column| center_distance(game, column));
+    columns
+}
+
+/// Everything the active player may do: the legal columns and, in PopOut, popping any of their
+/// pieces in the bottom row after them. Without gravity every empty cell, from the center out.
+fn legal_actions<B: BoardBackend>(game: &Game<B>) -> Vec<Action> {
+    if !game.gravity {
+        let height = game.board.height();
+        let mut cells: Vec<(usize, usize)> = (0..game.board.width())
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .filter(|&(x, y)| game.board.occupance(x, y) == Some(0))
+            .collect();
+        cells.sort_by_key(|&(x, y)| center_distance(game, x) + (2 * y).abs_diff(height - 1));
+        return cells
+            .into_iter()
+            .map(|(x, y)| Action::PlaceAt(x, y))
+            .collect();
+    }
+    let mut actions: Vec<Action> = legal_columns(game).into_iter().map(Action::Place).collect();
+    if game.pop_out {
+        let mut pops: Vec<usize> = (0..game.board.width())
+            .filter(|&x| game.board.occupance(x, 0) == Some(game.active_player + 1))
+            .collect();
+        pops.sort_by_key(|&column| center_distance(game, column));
+        actions.extend(pops.into_iter().map(Action::Pop));
+    }
+    actions
+}
+
+/// Distance of `column` from the center, on a 3D board from the center of the layer.
+fn center_distance<B: BoardBackend>(game: &Game<B>, column: usize) -> usize {
+    let (x, z) = game.pillar_position(column);
+    (2 * x).abs_diff(game.width().saturating_sub(1)) + (2 * z).abs_diff(game.depth() - 1)
+}
+
+/// Any of the engines, so seats can hold whichever the player picked.
+pub enum Ai {
+    /// Negamax with alpha-beta pruning, see `Minimax`.
+    Minimax(Minimax),
+    /// Monte Carlo Tree Search, see `Mcts`.
+    Mcts(Mcts),
+}
+impl Ai {
+    /// A `Minimax` that searches `depth` moves ahead.
+    pub fn minimax(depth: usize) -> Self {
+        Ai::Minimax(Minimax::new(depth))
+    }
+
+    /// An `Mcts` within `budget`, reproducible with the same `seed`.
+    pub fn mcts(budget: Budget, seed: u64) -> Self {
+        Ai::Mcts(Mcts::new(budget, seed))
+    }
+
+    /// Picks a move for the active player, a pop only in PopOut games. Fits the
+    /// `input_function` of `take_action`.
+    pub fn choose_action(&mut self, game: &Game) -> Res<Action> {
+        match self {
+            Ai::Minimax(minimax) => minimax.choose_action(game),
+            Ai::Mcts(mcts) => mcts.choose_action(game),
+        }
+    }
+}
+impl Player for Ai {
+    fn choose_action(&mut self, game: &Game) -> Res<Action> {
+        Ai::choose_action(self, game)
+    }
+}
@@ -0,0 +1,229 @@
+//! Monte Carlo Tree Search, see [`Mcts`].
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    ai::{legal_actions, search_on_bitboard, Search},
+    Action, BoardBackend, Game, Res, TurnOutcome,
+};
+
+const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+/// A playout stops as a draw after this many moves per cell of the board.
+const PLAYOUT_ROUNDS: usize = 4;
+
+/// How long a search may run, either a number of playouts or a time limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Budget {
+    /// Stops after this many playouts, which gives the same move on every machine.
+    Playouts(usize),
+    /// Stops once the time is up.
+    Time(Duration),
+}
+
+/// Small xorshift generator, so searches can be reproduced from a seed.
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+}
+impl Rng {
+    /// A generator whose numbers only depend on `seed`.
+    pub fn new(seed: u64) -> Self {
+        // Scramble the seed once so that small seeds do not start with a run of zeros.
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+        Rng {
+            state: if state == 0 { 1 } else { state },
+        }
+    }
+
+    /// The next number of the sequence.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Uniform-ish number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+struct Node {
+    action: Action,
+    mover: u32,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Action>,
+    terminal: Option<Vec<f64>>,
+    visits: u32,
+    rewards: Vec<f64>,
+}
+
+/// Monte Carlo Tree Search with UCT selection and random playouts.
+///
+/// Every node keeps one accumulated reward per player, so each player picks the child that is
+/// best for themselves and the search works for any `player_count`.
+pub struct Mcts {
+    budget: Budget,
+    rng: Rng,
+}
+impl Mcts {
+    /// A search within `budget`, the random playouts follow `seed`.
+    pub fn new(budget: Budget, seed: u64) -> Self {
+        Mcts {
+            budget,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Picks the most visited move after the budget is used up.
+    pub fn choose_action(&mut self, game: &Game) -> Res<Action> {
+        search_on_bitboard(self, game)
+    }
+
+    fn playout<B: BoardBackend>(&mut self, game: &mut Game<B>, player_count: usize) -> Vec<f64> {
+        // Pops can keep a PopOut game going forever, a playout that long counts as a draw
+        for _ in 0..PLAYOUT_ROUNDS * game.board.width() * game.board.height() {
+            let actions = legal_actions(game);
+            if actions.is_empty() {
+                break;
+            }
+            let action = actions[self.rng.below(actions.len())];
+            let _ = game.play(action);
+            game.end_turn();
+            if let Some(rewards) = outcome_rewards(game.status(), player_count) {
+                return rewards;
+            }
+        }
+        vec![1.0 / player_count as f64; player_count]
+    }
+}
+
+impl Search for Mcts {
+    fn search<B: BoardBackend>(&mut self, game: Game<B>) -> Res<Action> {
+        let root_actions = legal_actions(&game);
+        match root_actions.len() {
+            0 => return Err("There is no column left to place a piece in".into()),
+            1 => return Ok(root_actions[0]),
+            _ => {}
+        }
+
+        let player_count = game.player_count as usize;
+        let mut nodes = vec![Node {
+            action: root_actions[0],
+            mover: game.active_player,
+            parent: None,
+            children: Vec::new(),
+            untried: root_actions,
+            terminal: None,
+            visits: 0,
+            rewards: vec![0.0; player_count],
+        }];
+
+        let start = Instant::now();
+        let mut playouts = 0;
+        loop {
+            match self.budget {
+                Budget::Playouts(n) if playouts >= n.max(1) => break,
+                Budget::Time(t) if playouts > 0 && start.elapsed() >= t => break,
+                _ => {}
+            }
+            playouts += 1;
+
+            let mut game = game.clone();
+            let mut node = 0;
+
+            // Selection
+            while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+                node = select_child(&nodes, node);
+                let _ = game.play(nodes[node].action);
+                game.end_turn();
+            }
+
+            // Expansion
+            if nodes[node].terminal.is_none() && !nodes[node].untried.is_empty() {
+                let index = self.rng.below(nodes[node].untried.len());
+                let action = nodes[node].untried.swap_remove(index);
+                let mover = game.active_player;
+                let _ = game.play(action);
+                game.end_turn();
+                let terminal = outcome_rewards(game.status(), player_count);
+                let untried = if terminal.is_some() {
+                    Vec::new()
+                } else {
+                    legal_actions(&game)
+                };
+                nodes.push(Node {
+                    action,
+                    mover,
+                    parent: Some(node),
+                    children: Vec::new(),
+                    untried,
+                    terminal,
+                    visits: 0,
+                    rewards: vec![0.0; player_count],
+                });
+                let child = nodes.len() - 1;
+                nodes[node].children.push(child);
+                node = child;
+            }
+
+            // Playout
+            let rewards = match &nodes[node].terminal {
+                Some(rewards) => rewards.clone(),
+                None => self.playout(&mut game, player_count),
+            };
+
+            // Backup
+            let mut current = Some(node);
+            while let Some(index) = current {
+                let node = &mut nodes[index];
+                node.visits += 1;
+                for (total, reward) in node.rewards.iter_mut().zip(&rewards) {
+                    *total += reward;
+                }
+                current = node.parent;
+            }
+        }
+
+        nodes[0]
+            .children
+            .iter()
+            .max_by_key(|&&child| nodes[child].visits)
+            .map(|&child| nodes[child].action)
+            .ok_or_else(|| "The search did not expand any move".into())
+    }
+}
+
+fn select_child(nodes: &[Node], parent: usize) -> usize {
+    let log_visits = (nodes[parent].visits.max(1) as f64).ln();
+    let mut best = nodes[parent].children[0];
+    let mut best_value = f64::NEG_INFINITY;
+    for &child in &nodes[parent].children {
+        let node = &nodes[child];
+        let visits = node.visits.max(1) as f64;
+        let value =
+            node.rewards[node.mover as usize] / visits + EXPLORATION * (log_visits / visits).sqrt();
+        if value > best_value {
+            best_value = value;
+            best = child;
+        }
+    }
+    best
+}
+
+fn outcome_rewards(outcome: TurnOutcome, player_count: usize) -> Option<Vec<f64>> {
+    match outcome {
+        TurnOutcome::Continue => None,
+        TurnOutcome::Draw => Some(vec![1.0 / player_count as f64; player_count]),
+        TurnOutcome::Win(player) => {
+            let mut rewards = vec![0.0; player_count];
+            rewards[player as usize] = 1.0;
+            Some(rewards)
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Negamax search with alpha-beta pruning, see [`Minimax`].
+
+use crate::{
+    ai::{legal_actions, search_on_bitboard, Search},
+    Action, BoardBackend, Direction, Game, Res, TurnOutcome,
+};
+
+const WIN_SCORE: i64 = 1 << 60;
+const LINE_DIRECTIONS: [(i64, i64, i64); 4] = [(1, 0, 0), (0, 1, 0), (1, 1, 0), (1, -1, 0)];
+
+/// Negamax search with alpha-beta pruning.
+///
+/// With more than two players the search is paranoid: the player to move at the root plays
+/// against a coalition of everyone else, so the sign only flips when the turn passes between
+/// the root player and the coalition.
+pub struct Minimax {
+    depth: usize,
+}
+impl Minimax {
+    /// Searches `depth` moves ahead, at least one.
+    pub fn new(depth: usize) -> Self {
+        Minimax {
+            depth: depth.max(1),
+        }
+    }
+
+    /// Picks the best move for the active player.
+    pub fn choose_action(&mut self, game: &Game) -> Res<Action> {
+        search_on_bitboard(self, game)
+    }
+
+    /// Plays `action` for the active player and returns the score from the view of their side.
+    fn score_move<B: BoardBackend>(
+        &self,
+        game: &mut Game<B>,
+        root: u32,
+        action: Action,
+        depth: usize,
+        alpha: i64,
+        beta: i64,
+    ) -> i64 {
+        let mover = game.active_player;
+        if game.play(action).is_err() {
+            return -WIN_SCORE;
+        }
+        game.end_turn();
+        let score = match game.status() {
+            // A pop can also complete a line of the other side
+            TurnOutcome::Win(winner) if (winner == root) == (mover == root) => {
+                WIN_SCORE + depth as i64
+            }
+            TurnOutcome::Win(_) => -WIN_SCORE - depth as i64,
+            TurnOutcome::Draw => 0,
+            TurnOutcome::Continue if depth <= 1 => {
+                let score = evaluate(game, root);
+                if mover == root {
+                    score
+                } else {
+                    -score
+                }
+            }
+            TurnOutcome::Continue => {
+                if (game.active_player == root) == (mover == root) {
+                    self.negamax(game, root, depth - 1, alpha, beta)
+                } else {
+                    -self.negamax(game, root, depth - 1, -beta, -alpha)
+                }
+            }
+        };
+        game.undo();
+        score
+    }
+
+    fn negamax<B: BoardBackend>(
+        &self,
+        game: &mut Game<B>,
+        root: u32,
+        depth: usize,
+        mut alpha: i64,
+        beta: i64,
+    ) -> i64 {
+        let mut best = -WIN_SCORE - depth as i64 - 1;
+        for action in legal_actions(game) {
+            let score = self.score_move(game, root, action, depth, alpha, beta);
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+}
+
+impl Search for Minimax {
+    fn search<B: BoardBackend>(&mut self, mut game: Game<B>) -> Res<Action> {
+        let root = game.active_player;
+        let mut alpha = -WIN_SCORE - self.depth as i64 - 1;
+        let beta = WIN_SCORE + self.depth as i64 + 1;
+        let mut best_action = None;
+
+        for action in legal_actions(&game) {
+            let score = self.score_move(&mut game, root, action, self.depth, alpha, beta);
+            if best_action.is_none() || score > alpha {
+                alpha = score;
+                best_action = Some(action);
+            }
+        }
+
+        best_action.ok_or_else(|| "There is no column left to place a piece in".into())
+    }
+}
+
+/// Scores every window of `connect_size` cells that only one player occupies. Windows the
+/// root player could still complete count for them, all others count against them.
+fn evaluate<B: BoardBackend>(game: &Game<B>, root: u32) -> i64 {
+    let board = &game.board;
+    let topology = game.topology;
+    let (width, height, depth) = (game.width(), board.height(), game.depth() as i64);
+    let connect_size = game.connect_size as i64;
+    if connect_size == 0 {
+        return 0;
+    }
+
+    let directions: &[(i64, i64, i64)] = match depth {
+        1 => &LINE_DIRECTIONS,
+        _ => &Direction::AXES_3D,
+    };
+    let mut score = 0;
+    for &(x_dir, y_dir, z_dir) in directions {
+        // Nobody can complete a window longer than the way around the board
+        if topology
+            .period((x_dir, y_dir), width, height)
+            .is_some_and(|period| period < game.connect_size)
+        {
+            continue;
+        }
+        for (x, y, z) in (0..width as i64)
+            .flat_map(|x| (0..height as i64).flat_map(move |y| (0..depth).map(move |z| (x, y, z))))
+        {
+            let end_x = x + x_dir * (connect_size - 1);
+            let end_y = y + y_dir * (connect_size - 1);
+            let end_z = z + z_dir * (connect_size - 1);
+            if topology.wrap(end_x, end_y, width, height).is_none() || !(0..depth).contains(&end_z)
+            {
+                continue;
+            }
+
+            let mut owner = 0;
+            let mut pieces = 0;
+            let mut open = true;
+            for i in 0..connect_size {
+                let occupance = match topology
+                    .wrap(x + x_dir * i, y + y_dir * i, width, height)
+                    .and_then(|(x, y)| board.occupance(game.pillar(x, (z + z_dir * i) as usize), y))
+                {
+                    // A blocked cell, nobody can complete the window
+                    None => {
+                        open = false;
+                        break;
+                    }
+                    Some(occupance) => occupance,
+                };
+                if occupance == 0 {
+                    continue;
+                }
+                if owner != 0 && owner != occupance {
+                    open = false;
+                    break;
+                }
+                owner = occupance;
+                pieces += 1;
+            }
+
+            if open && pieces > 0 {
+                let weight = 1i64 << (2 * pieces.min(16));
+                if owner - 1 == root {
+                    score += weight;
+                } else {
+                    score -= weight;
+                }
+            }
+        }
+    }
+    score
+}
@@ -0,0 +1,49 @@
+use std::{env, process, time::Duration};
+
+use connect_n::{
+    net::server::{self, ServerConfig, TimeoutPolicy},
+    Res,
+};
+
+const USAGE: &str = "\
+Usage: connect-n-server [OPTIONS]
+
+Runs games for connect-n clients that use '--server'.
+
+Options:
+  --address <address>            Address to listen on (default 0.0.0.0:7878)
+  --timeout <seconds>            How long a game waits for a player that left (default 60)
+  --on-timeout <forfeit|skip>    What happens when they do not rejoin in time (default forfeit)
+  -h, --help                     Print this help";
+
+fn main() -> Res<()> {
+    let mut address = String::from("0.0.0.0:7878");
+    let mut config = ServerConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("-h" | "--help", _) => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            ("--address", Some(value)) => address = value,
+            ("--timeout", Some(value)) => match value.parse() {
+                Ok(seconds) => config.timeout = Duration::from_secs(seconds),
+                Err(_) => fail(&format!("Invalid timeout '{}'", value)),
+            },
+            ("--on-timeout", Some(value)) => match value.as_str() {
+                "forfeit" => config.policy = TimeoutPolicy::Forfeit,
+                "skip" => config.policy = TimeoutPolicy::Skip,
+                _ => fail(&format!("Invalid timeout policy '{}'", value)),
+            },
+            _ => fail(&format!("Unknown argument '{}'", arg)),
+        }
+    }
+    println!("Serving lobbies on {}", address);
+    server::run(&address, config)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
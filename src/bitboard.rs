@@ -0,0 +1,466 @@
+//! Boards stored as one bitmask per player, for searches that play millions of moves.
+
+use crate::{BoardBackend, Direction, Topology};
+
+/// Fixed size bit storage for a `BitBoard`. Bit `i` moves to bit `i - n` when shifted by `n`.
+pub trait Bits: Clone {
+    /// Largest number of bits this storage can hold, `None` if it grows with the board.
+    const CAPACITY: Option<usize>;
+
+    /// Storage for `bits` bits, all unset.
+    fn zero(bits: usize) -> Self;
+
+    /// Whether bit `i` is set.
+    fn get(&self, i: usize) -> bool;
+
+    /// Sets bit `i`.
+    fn set(&mut self, i: usize);
+
+    /// Unsets bit `i`.
+    fn unset(&mut self, i: usize);
+
+    /// The bits set in both.
+    fn and(&self, other: &Self) -> Self;
+
+    /// Moves every bit `n` places down, the lowest ones fall off.
+    fn shift_down(&self, n: usize) -> Self;
+
+    /// Whether no bit is set.
+    fn is_zero(&self) -> bool;
+}
+
+macro_rules! impl_bits_for_int {
+    ($int:ty) => {
+        impl Bits for $int {
+            const CAPACITY: Option<usize> = Some(<$int>::BITS as usize);
+
+            fn zero(_bits: usize) -> Self {
+                0
+            }
+
+            fn get(&self, i: usize) -> bool {
+                self >> i & 1 == 1
+            }
+
+            fn set(&mut self, i: usize) {
+                *self |= 1 << i
+            }
+
+            fn unset(&mut self, i: usize) {
+                *self &= !(1 << i)
+            }
+
+            fn and(&self, other: &Self) -> Self {
+                self & other
+            }
+
+            fn shift_down(&self, n: usize) -> Self {
+                self.checked_shr(n as u32).unwrap_or(0)
+            }
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+        }
+    };
+}
+impl_bits_for_int!(u64);
+impl_bits_for_int!(u128);
+
+/// Bit storage for boards that do not fit into 128 bits.
+#[derive(Clone, PartialEq)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+impl Bits for BitSet {
+    const CAPACITY: Option<usize> = None;
+
+    fn zero(bits: usize) -> Self {
+        BitSet {
+            words: vec![0; bits.div_ceil(64)],
+        }
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.words
+            .get(i / 64)
+            .is_some_and(|word| word >> (i % 64) & 1 == 1)
+    }
+
+    fn set(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64)
+    }
+
+    fn unset(&mut self, i: usize) {
+        self.words[i / 64] &= !(1 << (i % 64))
+    }
+
+    fn and(&self, other: &Self) -> Self {
+        BitSet {
+            words: self
+                .words
+                .iter()
+                .zip(&other.words)
+                .map(|(a, b)| a & b)
+                .collect(),
+        }
+    }
+
+    fn shift_down(&self, n: usize) -> Self {
+        let (word_shift, bit_shift) = (n / 64, n % 64);
+        let words = (0..self.words.len())
+            .map(|i| {
+                let low = self.words.get(i + word_shift).copied().unwrap_or(0);
+                let high = self.words.get(i + word_shift + 1).copied().unwrap_or(0);
+                if bit_shift == 0 {
+                    low
+                } else {
+                    low >> bit_shift | high << (64 - bit_shift)
+                }
+            })
+            .collect();
+        BitSet { words }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+}
+
+/// Board with one bitmask per player. Columns are stored one after another with an always empty
+/// guard bit on top, so shifting a mask never carries a line from one column into the next.
+#[derive(Clone)]
+pub struct BitBoard<T: Bits> {
+    width: usize,
+    height: usize,
+    players: Vec<T>,
+    pieces: usize,
+    /// Blocked cells are in no player's mask, so lines end at them.
+    blocked: T,
+    blocked_count: usize,
+}
+impl<T: Bits> BitBoard<T> {
+    /// Bits a board of this size needs, guard bits included.
+    pub fn required_bits(width: usize, height: usize) -> usize {
+        width * (height + 1)
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        x * (self.height + 1) + y
+    }
+
+    fn player_at(&self, i: usize) -> Option<usize> {
+        self.players.iter().position(|bits| bits.get(i))
+    }
+}
+impl<T: Bits> BoardBackend for BitBoard<T> {
+    fn new(width: usize, height: usize) -> Self {
+        let bits = Self::required_bits(width, height);
+        if let Some(capacity) = T::CAPACITY {
+            assert!(
+                bits <= capacity,
+                "A {}x{} board needs {} bits but the storage only holds {}",
+                width,
+                height,
+                bits,
+                capacity
+            );
+        }
+        BitBoard {
+            width,
+            height,
+            players: Vec::new(),
+            pieces: 0,
+            blocked: T::zero(bits),
+            blocked_count: 0,
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn occupance(&self, x: usize, y: usize) -> Option<u32> {
+        if self.is_blocked(x, y) || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(match self.player_at(self.index(x, y)) {
+            None => 0,
+            Some(player) => player as u32 + 1,
+        })
+    }
+
+    fn occupy(&mut self, x: usize, y: usize, player: u32) {
+        if self.is_blocked(x, y) || x >= self.width || y >= self.height {
+            return;
+        }
+        self.clear(x, y);
+        let bits = Self::required_bits(self.width, self.height);
+        while self.players.len() <= player as usize {
+            self.players.push(T::zero(bits));
+        }
+        let i = self.index(x, y);
+        self.players[player as usize].set(i);
+        self.pieces += 1;
+    }
+
+    fn clear(&mut self, x: usize, y: usize) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let i = self.index(x, y);
+        if let Some(player) = self.player_at(i) {
+            self.players[player].unset(i);
+            self.pieces -= 1;
+        }
+    }
+
+    fn block(&mut self, x: usize, y: usize) {
+        if self.is_blocked(x, y) || x >= self.width || y >= self.height {
+            return;
+        }
+        self.clear(x, y);
+        let i = self.index(x, y);
+        self.blocked.set(i);
+        self.blocked_count += 1;
+    }
+
+    fn is_blocked(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.blocked.get(self.index(x, y))
+    }
+
+    fn is_full(&self) -> bool {
+        self.pieces + self.blocked_count == self.width * self.height
+    }
+
+    fn has_line_through(
+        &self,
+        x: usize,
+        y: usize,
+        connect_size: usize,
+        topology: Topology,
+    ) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        // Shifted masks do not wrap around, so wrapped boards walk the lines cell by cell
+        if topology != Topology::Flat {
+            return Direction::AXES
+                .iter()
+                .any(|&axis| self.get_axis_length(x, y, axis, topology) >= connect_size);
+        }
+        let i = self.index(x, y);
+        let bits = match self.player_at(i) {
+            None => return false,
+            Some(player) => &self.players[player],
+        };
+
+        // Vertical, horizontal and both diagonals
+        let column = self.height + 1;
+        [1, column, column + 1, column - 1]
+            .into_iter()
+            .any(|shift| {
+                // Every bit still set in `starts` begins a line of `connect_size` pieces
+                let mut starts = bits.clone();
+                for n in 1..connect_size {
+                    starts = starts.and(&bits.shift_down(n * shift));
+                    if starts.is_zero() {
+                        return false;
+                    }
+                }
+                (0..connect_size)
+                    .take_while(|n| n * shift <= i)
+                    .any(|n| starts.get(i - n * shift))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ai::mcts::Rng, Action, Board, Game};
+
+    struct Rules {
+        width: usize,
+        height: usize,
+        depth: usize,
+        connect_size: usize,
+        player_count: u32,
+        pop_out: bool,
+        topology: Topology,
+        blocked: &'static [(usize, usize)],
+    }
+    impl Rules {
+        fn game<B: BoardBackend>(&self) -> Game<B> {
+            Game::with_backend(
+                self.player_count,
+                self.connect_size,
+                self.width,
+                self.height,
+            )
+            .with_depth(self.depth)
+            .with_pop_out(self.pop_out)
+            .with_topology(self.topology)
+            .with_blocked_cells(self.blocked)
+        }
+    }
+
+    const RULES: [Rules; 8] = [
+        Rules {
+            width: 7,
+            height: 6,
+            depth: 1,
+            connect_size: 4,
+            player_count: 2,
+            pop_out: false,
+            topology: Topology::Flat,
+            blocked: &[],
+        },
+        Rules {
+            width: 6,
+            height: 5,
+            depth: 1,
+            connect_size: 4,
+            player_count: 3,
+            pop_out: true,
+            topology: Topology::Cylinder,
+            blocked: &[],
+        },
+        Rules {
+            width: 5,
+            height: 5,
+            depth: 1,
+            connect_size: 3,
+            player_count: 2,
+            pop_out: true,
+            topology: Topology::Torus,
+            blocked: &[],
+        },
+        // A pyramid, with blocked cells above empty ones
+        Rules {
+            width: 7,
+            height: 4,
+            depth: 1,
+            connect_size: 4,
+            player_count: 2,
+            pop_out: false,
+            topology: Topology::Flat,
+            blocked: &[
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (1, 2),
+                (1, 3),
+                (2, 3),
+                (4, 3),
+                (5, 2),
+                (5, 3),
+                (6, 1),
+                (6, 2),
+                (6, 3),
+            ],
+        },
+        Rules {
+            width: 6,
+            height: 6,
+            depth: 1,
+            connect_size: 4,
+            player_count: 2,
+            pop_out: true,
+            topology: Topology::Torus,
+            blocked: &[(1, 2), (2, 2), (4, 0), (5, 3)],
+        },
+        Rules {
+            width: 3,
+            height: 3,
+            depth: 3,
+            connect_size: 3,
+            player_count: 2,
+            pop_out: true,
+            topology: Topology::Flat,
+            blocked: &[],
+        },
+        // 90 bits, too many for u64
+        Rules {
+            width: 10,
+            height: 8,
+            depth: 1,
+            connect_size: 4,
+            player_count: 2,
+            pop_out: true,
+            topology: Topology::Flat,
+            blocked: &[],
+        },
+        // Gomoku sized, 240 bits are too many for u128
+        Rules {
+            width: 15,
+            height: 15,
+            depth: 1,
+            connect_size: 5,
+            player_count: 2,
+            pop_out: false,
+            topology: Topology::Flat,
+            blocked: &[],
+        },
+    ];
+
+    fn cells<B: BoardBackend>(game: &Game<B>) -> Vec<Option<u32>> {
+        let board = game.board();
+        (0..board.width())
+            .flat_map(|x| (0..board.height()).map(move |y| board.occupance(x, y)))
+            .collect()
+    }
+
+    /// Plays the same random games on `Board` and `B`, they have to agree after every move.
+    fn check_backend<B: BoardBackend>(i: usize, rules: &Rules) {
+        for seed in 0..50 {
+            let mut rng = Rng::new(seed);
+            let mut board: Game<Board> = rules.game();
+            let mut bits: Game<B> = rules.game();
+
+            // Pops could go on forever, the games are cut off after enough moves
+            for _ in 0..500 {
+                if board.is_over() {
+                    break;
+                }
+                let column = rng.below(board.board().width());
+                let action = match rng.below(4) {
+                    0 => Action::Pop(column),
+                    _ => Action::Place(column),
+                };
+                let played = board.play(action);
+                assert_eq!(bits.play(action), played, "rules {} seed {}", i, seed);
+                assert_eq!(bits.status(), board.status());
+                assert_eq!(cells(&bits), cells(&board));
+                board.end_turn();
+                bits.end_turn();
+            }
+
+            // Taking every move back leaves the empty board on both
+            while board.undo().is_some() {
+                bits.undo();
+            }
+            let empty = cells(&rules.game::<Board>());
+            assert_eq!(cells(&board), empty);
+            assert_eq!(cells(&bits), empty);
+        }
+    }
+
+    #[test]
+    fn random_games_agree_on_every_backend() {
+        for (i, rules) in RULES.iter().enumerate() {
+            let bits = BitBoard::<u64>::required_bits(rules.width * rules.depth, rules.height);
+            if bits <= 64 {
+                check_backend::<BitBoard<u64>>(i, rules);
+            }
+            if bits <= 128 {
+                check_backend::<BitBoard<u128>>(i, rules);
+            }
+            check_backend::<BitBoard<BitSet>>(i, rules);
+        }
+    }
+}
@@ -1,64 +1,306 @@
-use std::io::stdin;
+use std::{error::Error, fmt::Display, io::stdin};
 
-use crate::{take_turn, Game, Res};
+use connect_n::{
+    net::SeatEvent, play_turn, Action, BoardBackend, Cell, Game, GameError, MoveKind, Player, Res,
+    TurnOutcome,
+};
 
-pub fn cli_game(
-    player_count: u32,
-    connect_size: usize,
-    board_width: usize,
-    board_height: usize,
+use crate::config::GameConfig;
+
+#[derive(Debug)]
+enum CliCommand {
+    Play(Action),
+    Undo,
+    Redo,
+    Save(String),
+    Load(String),
+}
+impl Display for CliCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The command {:?} has to be run by the game loop", self)
+    }
+}
+/// Commands other than playing a piece change more than the player may, so `CliHuman` hands
+/// them to the game loop as an error.
+impl Error for CliCommand {}
+
+/// A human sitting at the terminal.
+struct CliHuman;
+impl Player for CliHuman {
+    fn choose_action(&mut self, game: &Game) -> Res<Action> {
+        match get_cli_input(game)? {
+            CliCommand::Play(action) => Ok(action),
+            command => Err(Box::new(command)),
+        }
+    }
+}
+
+pub fn cli_game(mut config: GameConfig) -> Res<()> {
+    let (mut game, mut players) = config.start(&mut human)?;
+    loop {
+        play_game(&mut config, &mut game, &mut players)?;
+        if config.is_networked() || !ask_play_again()? {
+            return Ok(());
+        }
+        game = config.new_game();
+        players = config.create_players(&mut human)?;
+    }
+}
+
+fn human() -> Box<dyn Player> {
+    Box::new(CliHuman)
+}
+
+fn play_game(
+    config: &mut GameConfig,
+    game: &mut Game,
+    players: &mut Vec<Box<dyn Player>>,
 ) -> Res<()> {
-    let mut game = Game::new(player_count, connect_size, board_width, board_height);
-    let mut game_over = false;
-    while !game_over {
-        loop {
-            match take_turn(&mut game, &mut get_cli_input) {
-                Ok(b) => {
-                    game_over = b;
-                    break;
+    // Only games decided here are rated, not ones that were already over when loaded
+    let mut decided_here = false;
+    while !game.is_over() {
+        let player = game.active_player();
+        let command = match play_turn(game, players) {
+            Ok(_) => {
+                decided_here = game.is_over();
+                match game.history().last() {
+                    Some(_) if config.is_human(player) => {}
+                    Some(last_move) if last_move.kind == MoveKind::Pop => println!(
+                        "Player {} popped a piece out of {}",
+                        player + 1,
+                        describe_column(game, last_move.x)
+                    ),
+                    Some(last_move) => println!(
+                        "Player {} placed a piece in {}",
+                        player + 1,
+                        describe_column(game, last_move.x)
+                    ),
+                    None => {}
+                }
+                continue;
+            }
+            Err(e) => match e.downcast::<CliCommand>() {
+                Ok(command) => *command,
+                Err(e) if e.is::<GameError>() => {
+                    println!("{}", e);
+                    continue;
+                }
+                Err(e) => match e.downcast::<SeatEvent>() {
+                    Ok(event) => {
+                        println!("{}", event);
+                        event.apply(game)?;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                },
+            },
+        };
+
+        match command {
+            CliCommand::Play(_) => {}
+            CliCommand::Undo | CliCommand::Redo | CliCommand::Load(_) if config.is_networked() => {
+                println!("Moves can not be taken back or loaded in a network game.")
+            }
+            CliCommand::Undo => {
+                if game.undo().is_none() {
+                    println!("There is no move to undo.")
                 }
-                Err(e) => println!("{}", e),
+                while !config.is_human(game.active_player()) && game.undo().is_some() {}
             }
+            CliCommand::Redo => match game.redo() {
+                Some(_) => while !config.is_human(game.active_player()) && game.redo().is_some() {},
+                None => println!("There is no move to redo."),
+            },
+            CliCommand::Save(path) => match game.save(&path) {
+                Ok(()) => println!("Saved the game to {}", path),
+                Err(e) => println!("Could not save the game: {}", e),
+            },
+            CliCommand::Load(path) => match load(config, &path) {
+                Ok(loaded) => {
+                    *game = loaded;
+                    *players = config.create_players(&mut human)?;
+                    println!("Loaded the game from {}", path);
+                }
+                Err(e) => println!("Could not load the game: {}", e),
+            },
+        }
+    }
+    match game.status() {
+        TurnOutcome::Win(player) => println!("Player {} won!!{}", player + 1, show_board(game)),
+        TurnOutcome::Draw => println!("The board is full. It's a draw!!{}", show_board(game)),
+        TurnOutcome::Continue => {}
+    }
+    if decided_here {
+        if let Err(e) = config.rate(game) {
+            println!("Could not update the ratings: {}", e);
         }
     }
-    println!(
-        "Player {} won!!{}",
-        if game.active_player == 0 {
-            game.player_count
-        } else {
-            game.active_player
-        },
-        game.board
-    );
     Ok(())
 }
 
-fn get_cli_input(game: &Game) -> Res<usize> {
+/// Loads a saved game and takes over its rules, unless the seats can not play them.
+fn load(config: &mut GameConfig, path: &str) -> Res<Game> {
+    let game = Game::load(path)?;
+    let mut adopted = config.clone();
+    adopted.adopt_rules(&game);
+    adopted.validate()?;
+    *config = adopted;
+    Ok(game)
+}
+
+/// The board as text. A 3D board is shown one layer after the other from the top, each seen from
+/// above with its front row of pillars at the bottom.
+fn show_board(game: &Game) -> String {
+    if game.depth() == 1 {
+        return game.board().to_string();
+    }
+    let mut text = String::new();
+    for y in (0..game.board().height()).rev() {
+        text += &format!("\nLayer {}", y);
+        for z in (0..game.depth()).rev() {
+            let row: Vec<&Cell> = (0..game.width())
+                .filter_map(|x| game.board().get_cell(game.pillar(x, z), y))
+                .collect();
+            text += &format!("\n{:?}", row);
+        }
+    }
+    text
+}
+
+/// Names the column of a move, as the pillar it is on a 3D board.
+fn describe_column(game: &Game, column: usize) -> String {
+    match game.pillar_position(column) {
+        (x, z) if game.depth() > 1 => format!("pillar {} {}", x, z),
+        _ => format!("column {}", column),
+    }
+}
+
+fn ask_play_again() -> Res<bool> {
+    println!("Do you want to play again? (y/n): ");
+
+    let mut buffer = String::new();
+    loop {
+        if stdin().read_line(&mut buffer)? == 0 {
+            return Ok(false);
+        }
+        match buffer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" | "q" | "quit" => return Ok(false),
+            _ => {
+                buffer = String::new();
+                println!("Please answer with y to play again or n to quit")
+            }
+        }
+    }
+}
+
+fn get_cli_input(game: &Game) -> Res<CliCommand> {
+    let target = match (game.gravity(), game.depth()) {
+        (false, _) => "x y",
+        (true, 1) => "column",
+        (true, _) => "x z",
+    };
+    let pop = if game.pop_out() {
+        format!("p<{}> to pop, ", target)
+    } else {
+        String::new()
+    };
     println!(
-        "Player {}, it is your turn.\nWhere do you want to place your piece?\n{}\n\ncolumn: ",
-        game.active_player + 1,
-        &game.board,
+        "Player {}, it is your turn.\nWhere do you want to place your piece?\n{}\n\n{} ({}u to undo, r to redo, save <file>, load <file>): ",
+        game.active_player() + 1,
+        show_board(game),
+        target,
+        pop,
     );
 
     let mut buffer = String::new();
-    let x: usize;
+    let command: CliCommand;
 
     loop {
-        stdin().read_line(&mut buffer)?;
-        match buffer.trim_end().parse::<usize>() {
-            Ok(n) if n < game.board.width => {
-                x = n;
+        if stdin().read_line(&mut buffer)? == 0 {
+            return Err("The input was closed before the game ended".into());
+        }
+        match buffer.trim_end() {
+            "u" => {
+                command = CliCommand::Undo;
                 break;
             }
-            _ => {
-                buffer = String::new();
-                println!(
-                    "Invalid Column. Please input a whole number between 0 and {}",
-                    game.board.width - 1
-                )
+            "r" => {
+                command = CliCommand::Redo;
+                break;
+            }
+            s if s.starts_with("save ") || s.starts_with("load ") => {
+                let path = s[5..].trim().to_string();
+                command = if s.starts_with("save ") {
+                    CliCommand::Save(path)
+                } else {
+                    CliCommand::Load(path)
+                };
+                break;
+            }
+            s if !game.gravity() => {
+                let coordinates: Vec<Result<usize, _>> =
+                    s.split_whitespace().map(str::parse::<usize>).collect();
+                match coordinates[..] {
+                    [Ok(x), Ok(y)] if x < game.board().width() && y < game.board().height() => {
+                        command = CliCommand::Play(Action::PlaceAt(x, y));
+                        break;
+                    }
+                    _ => {
+                        buffer = String::new();
+                        println!(
+                            "Invalid cell. Please input x between 0 and {} and y between 0 and {}, like '1 2', or u, r, save <file> or load <file>",
+                            game.board().width() - 1,
+                            game.board().height() - 1
+                        )
+                    }
+                }
+            }
+            s if game.depth() > 1 => {
+                let (action, pillar): (fn(usize) -> Action, &str) = match s.strip_prefix('p') {
+                    Some(pillar) if game.pop_out() => (Action::Pop, pillar),
+                    _ => (Action::Place, s),
+                };
+                let coordinates: Vec<Result<usize, _>> =
+                    pillar.split_whitespace().map(str::parse::<usize>).collect();
+                match coordinates[..] {
+                    [Ok(x), Ok(z)] if x < game.width() && z < game.depth() => {
+                        command = CliCommand::Play(action(game.pillar(x, z)));
+                        break;
+                    }
+                    _ => {
+                        buffer = String::new();
+                        println!(
+                            "Invalid pillar. Please input x between 0 and {} and z between 0 and {}, like '1 2'{}, u, r, save <file> or load <file>",
+                            game.width() - 1,
+                            game.depth() - 1,
+                            if game.pop_out() { ", p<x z>" } else { "" }
+                        )
+                    }
+                }
+            }
+            s => {
+                let (action, column): (fn(usize) -> Action, &str) = match s.strip_prefix('p') {
+                    Some(column) if game.pop_out() => (Action::Pop, column.trim()),
+                    _ => (Action::Place, s),
+                };
+                match column.parse::<usize>() {
+                    Ok(n) if n < game.board().width() => {
+                        command = CliCommand::Play(action(n));
+                        break;
+                    }
+                    _ => {
+                        buffer = String::new();
+                        println!(
+                            "Invalid Column. Please input a whole number between 0 and {}{}, u, r, save <file> or load <file>",
+                            game.board().width() - 1,
+                            if game.pop_out() { ", p<column>" } else { "" }
+                        )
+                    }
+                }
             }
         }
     }
 
-    Ok(x)
+    Ok(command)
 }
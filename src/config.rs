@@ -0,0 +1,656 @@
+use std::{
+    fmt::Display,
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use connect_n::{
+    ai::{mcts::Budget, Ai},
+    engine::{EngineProcess, Limit},
+    layout::Layout,
+    net::{self, Connection, HostFeed, Relay},
+    rating::Ratings,
+    validate_rules_3d, BoardBackend, Game, Player, Res, ScriptedPlayer, Topology,
+};
+
+pub const USAGE: &str = "\
+Usage: connect-n [OPTIONS]
+       connect-n engine [--seed <n>]    Speak the engine protocol on stdin and stdout
+       connect-n tournament [OPTIONS]   Play matches between computer players, see '--help'
+       connect-n ratings [--ratings <file>]    Print the leaderboard of every ruleset
+
+Options:
+  --players <n>          Number of players (default 2)
+  --connect <n>          Pieces in a row needed to win (default 4)
+  --width <n>            Board width (default 7)
+  --height <n>           Board height (default 6)
+  --depth <n>            Rows of pillars for a 3D board (default 1), e.g. Score Four with
+                         '--width 4 --height 4 --depth 4'
+  --popout               Play PopOut: instead of placing, pop your piece out of the bottom row
+  --no-gravity           Pieces go on any empty cell, e.g. tic-tac-toe with
+                         '--width 3 --height 3 --connect 3'
+  --topology <name>      flat (default) | cylinder: lines wrap from the left to the right edge
+                         | torus: they also wrap from the top to the bottom
+  --layout <file>        Board shape with blocked cells, one line per row from the top with '.'
+                         for a cell and '#' for a blocked one. Replaces '--width' and '--height'
+  --frontend <gui|cli>   Front end to play in (default gui if it was built in)
+  --seats <list>         Comma separated player type per seat (default all human)
+                         human | ai | minimax[:depth] | mcts[:millis | :<n>p]
+                         | script:<column>/<column>/... | remote
+                         | engine:<command>[:millis] (a bot speaking the engine protocol)
+  --host <address>       Host the game, clients take the remote seats
+  --join <address>       Join a hosted game, its rules replace the ones above
+  --server <address>     Play on a connect-n-server, in a new lobby with the rules above
+  --lobby <id>           Enter this lobby of the server instead
+  --rejoin <token>       Take back your seat in the lobby after the connection broke
+  --spectate             Watch the lobby instead of playing
+  --list                 List the lobbies of the server and quit
+  --seat <n>             Seat to claim when joining (default the first free one)
+  --seed <n>             Seed for the AI players
+  --load <file>          Continue a saved game, its rules replace the ones above
+  --names <list>         Comma separated names per seat for the ratings (default the seat type)
+  --ratings <file>       Rating file updated after local games (default connect-n-ratings.txt)
+  -h, --help             Print this help";
+
+pub const DEFAULT_RATINGS: &str = "connect-n-ratings.txt";
+const DEFAULT_MINIMAX_DEPTH: usize = 6;
+const DEFAULT_MCTS_MILLIS: u64 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Frontend {
+    #[cfg(feature = "gui")]
+    Gui,
+    Cli,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlayerKind {
+    Human,
+    Minimax(usize),
+    Mcts(Budget),
+    /// Plays these columns in order.
+    Script(Vec<usize>),
+    /// Taken by a client that joins the hosted game.
+    Remote,
+    /// An external program (with its arguments) speaking the engine protocol, and the time it
+    /// gets per move.
+    Engine(String, Duration),
+}
+impl Display for PlayerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlayerKind::Human => write!(f, "human"),
+            PlayerKind::Minimax(depth) => write!(f, "minimax:{}", depth),
+            PlayerKind::Mcts(Budget::Time(time)) => write!(f, "mcts:{}", time.as_millis()),
+            PlayerKind::Mcts(Budget::Playouts(n)) => write!(f, "mcts:{}p", n),
+            PlayerKind::Script(columns) => {
+                let columns: Vec<String> = columns.iter().map(usize::to_string).collect();
+                write!(f, "script:{}", columns.join("/"))
+            }
+            PlayerKind::Remote => write!(f, "remote"),
+            PlayerKind::Engine(command, time) => {
+                write!(f, "engine:{}:{}", command, time.as_millis())
+            }
+        }
+    }
+}
+impl PlayerKind {
+    pub fn parse(s: &str) -> Res<Self> {
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (s, None),
+        };
+        match (name, parameter) {
+            ("human", None) => Ok(PlayerKind::Human),
+            ("remote", None) => Ok(PlayerKind::Remote),
+            ("ai" | "minimax", None) => Ok(PlayerKind::Minimax(DEFAULT_MINIMAX_DEPTH)),
+            ("minimax", Some(depth)) => match depth.parse::<usize>() {
+                Ok(depth) if depth > 0 => Ok(PlayerKind::Minimax(depth)),
+                _ => Err(format!("Invalid minimax depth '{}'", depth).into()),
+            },
+            ("mcts", None) => Ok(PlayerKind::Mcts(Budget::Time(Duration::from_millis(
+                DEFAULT_MCTS_MILLIS,
+            )))),
+            ("mcts", Some(budget)) => {
+                let parsed = match budget.strip_suffix('p') {
+                    Some(playouts) => playouts.parse::<usize>().map(Budget::Playouts).ok(),
+                    None => budget
+                        .parse::<u64>()
+                        .map(|millis| Budget::Time(Duration::from_millis(millis)))
+                        .ok(),
+                };
+                match parsed {
+                    Some(Budget::Playouts(n)) if n > 0 => Ok(PlayerKind::Mcts(Budget::Playouts(n))),
+                    Some(Budget::Time(time)) if !time.is_zero() => {
+                        Ok(PlayerKind::Mcts(Budget::Time(time)))
+                    }
+                    _ => Err(format!("Invalid mcts budget '{}'", budget).into()),
+                }
+            }
+            ("script", Some(columns)) => columns
+                .split('/')
+                .map(|column| parse_number("script", column))
+                .collect::<Res<Vec<usize>>>()
+                .map(PlayerKind::Script),
+            ("engine", Some(program)) => {
+                let (program, millis) = match program.rsplit_once(':') {
+                    Some((program, millis)) if millis.parse::<u64>().is_ok() => {
+                        (program, parse_number("engine", millis)?)
+                    }
+                    _ => (program, DEFAULT_MCTS_MILLIS),
+                };
+                if program.trim().is_empty() {
+                    return Err("The engine needs a program to run".into());
+                }
+                Ok(PlayerKind::Engine(
+                    program.to_string(),
+                    Duration::from_millis(millis),
+                ))
+            }
+            _ => Err(format!("Unknown player type '{}'", s).into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Network {
+    /// Waits on this address for clients to take the remote seats.
+    Host(String),
+    /// Plays `seat`, or the first free one, of the game hosted at `address`.
+    Join { address: String, seat: Option<u32> },
+    /// Plays `seat`, or the first free one, in a lobby of a server. Without a lobby a new one is
+    /// created with the configured rules. With a session token the seat left earlier is rejoined.
+    Server {
+        address: String,
+        lobby: Option<u32>,
+        seat: Option<u32>,
+        token: Option<String>,
+    },
+    /// Watches a lobby of a server.
+    Spectate { address: String, lobby: u32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct GameConfig {
+    pub player_count: u32,
+    pub connect_size: usize,
+    pub board_width: usize,
+    pub board_height: usize,
+    /// 1 for a flat board.
+    pub board_depth: usize,
+    pub pop_out: bool,
+    pub gravity: bool,
+    pub topology: Topology,
+    pub blocked: Vec<(usize, usize)>,
+    pub frontend: Frontend,
+    pub seats: Vec<PlayerKind>,
+    pub seed: u64,
+    pub load: Option<String>,
+    pub network: Option<Network>,
+    /// Only list the lobbies of the server.
+    pub list: bool,
+    /// Names of the first seats in the ratings, the others are named after their `PlayerKind`.
+    pub names: Vec<String>,
+    pub ratings: String,
+}
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            player_count: 2,
+            connect_size: 4,
+            board_width: 7,
+            board_height: 6,
+            board_depth: 1,
+            pop_out: false,
+            gravity: true,
+            topology: Topology::Flat,
+            blocked: Vec::new(),
+            #[cfg(feature = "gui")]
+            frontend: Frontend::Gui,
+            #[cfg(not(feature = "gui"))]
+            frontend: Frontend::Cli,
+            seats: vec![PlayerKind::Human; 2],
+            seed: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0),
+            load: None,
+            network: None,
+            list: false,
+            names: Vec::new(),
+            ratings: DEFAULT_RATINGS.to_string(),
+        }
+    }
+}
+impl GameConfig {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Res<Self> {
+        let mut config = GameConfig::default();
+        let mut seats = None;
+        let mut host = None;
+        let mut join = None;
+        let mut seat = None;
+        let mut server = None;
+        let mut lobby = None;
+        let mut token = None;
+        let mut spectate = false;
+        let mut layout = None;
+        let mut args = args.into_iter();
+
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for '{}'", flag))
+            };
+            match flag.as_str() {
+                "--players" => config.player_count = parse_number(&flag, &value()?)?,
+                "--connect" => config.connect_size = parse_number(&flag, &value()?)?,
+                "--width" => config.board_width = parse_number(&flag, &value()?)?,
+                "--height" => config.board_height = parse_number(&flag, &value()?)?,
+                "--depth" => config.board_depth = parse_number(&flag, &value()?)?,
+                "--popout" => config.pop_out = true,
+                "--no-gravity" => config.gravity = false,
+                "--layout" => layout = Some(value()?),
+                "--topology" => {
+                    let name = value()?;
+                    config.topology = Topology::parse(&name)
+                        .ok_or_else(|| format!("Unknown topology '{}'", name))?
+                }
+                "--seed" => config.seed = parse_number(&flag, &value()?)?,
+                "--load" => config.load = Some(value()?),
+                "--host" => host = Some(value()?),
+                "--join" => join = Some(value()?),
+                "--server" => server = Some(value()?),
+                "--lobby" => lobby = Some(parse_number(&flag, &value()?)?),
+                "--rejoin" => token = Some(value()?),
+                "--spectate" => spectate = true,
+                "--list" => config.list = true,
+                "--names" => {
+                    config.names = value()?
+                        .split(',')
+                        .map(|name| name.trim().to_string())
+                        .collect()
+                }
+                "--ratings" => config.ratings = value()?,
+                "--seat" => match parse_number::<u32>(&flag, &value()?)? {
+                    0 => return Err("Seats are counted from 1".into()),
+                    n => seat = Some(n - 1),
+                },
+                "--frontend" => {
+                    config.frontend = match value()?.as_str() {
+                        #[cfg(feature = "gui")]
+                        "gui" => Frontend::Gui,
+                        #[cfg(not(feature = "gui"))]
+                        "gui" => return Err("This build does not include the gui".into()),
+                        "cli" => Frontend::Cli,
+                        other => return Err(format!("Unknown frontend '{}'", other).into()),
+                    }
+                }
+                "--seats" => {
+                    seats = Some(
+                        value()?
+                            .split(',')
+                            .map(|s| PlayerKind::parse(s.trim()))
+                            .collect::<Res<Vec<PlayerKind>>>()?,
+                    )
+                }
+                _ => return Err(format!("Unknown argument '{}'", flag).into()),
+            }
+        }
+
+        if seat.is_some() && join.is_none() && server.is_none() {
+            return Err("'--seat' only works together with '--join' or '--server'".into());
+        }
+        if (lobby.is_some() || spectate || config.list) && server.is_none() {
+            return Err("'--lobby', '--spectate' and '--list' need a '--server'".into());
+        }
+        if token.is_some() && (lobby.is_none() || seat.is_some() || spectate) {
+            return Err("'--rejoin' needs the '--lobby' of the seat and no '--seat'".into());
+        }
+        config.network = match (host, join, server) {
+            (None, None, None) => None,
+            (Some(address), None, None) => Some(Network::Host(address)),
+            (None, Some(address), None) => Some(Network::Join { address, seat }),
+            (None, None, Some(address)) if spectate => match lobby {
+                Some(lobby) => Some(Network::Spectate { address, lobby }),
+                None => return Err("'--spectate' needs the '--lobby' to watch".into()),
+            },
+            (None, None, Some(address)) => Some(Network::Server {
+                address,
+                lobby,
+                seat,
+                token,
+            }),
+            _ => return Err("Only one of '--host', '--join' and '--server' can be used".into()),
+        };
+        if let Some(path) = layout {
+            let layout = Layout::load(&path)?;
+            config.board_width = layout.width;
+            config.board_height = layout.height;
+            config.blocked = layout.blocked;
+        }
+        if let Some(path) = &config.load {
+            let game = Game::load(path)?;
+            config.adopt_rules(&game);
+        }
+        config.seats =
+            seats.unwrap_or_else(|| vec![PlayerKind::Human; config.player_count as usize]);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Res<()> {
+        validate_rules_3d(
+            self.player_count,
+            self.connect_size,
+            self.board_width,
+            self.board_height,
+            self.board_depth,
+        )?;
+        if self.pop_out && self.is_networked() {
+            return Err("PopOut can not be played over the network".into());
+        }
+        if self.topology != Topology::Flat && self.is_networked() {
+            return Err("Only flat boards can be played over the network".into());
+        }
+        if !self.blocked.is_empty() && self.is_networked() {
+            return Err("Boards with blocked cells can not be played over the network".into());
+        }
+        if self.board_depth > 1 {
+            if self.is_networked() {
+                return Err("3D boards can not be played over the network".into());
+            }
+            if !self.gravity {
+                return Err("3D boards need gravity".into());
+            }
+            if self.topology != Topology::Flat {
+                return Err("3D boards can not wrap around".into());
+            }
+            if !self.blocked.is_empty() {
+                return Err("Layouts are flat, they can not be used for 3D boards".into());
+            }
+        }
+        if !self.gravity {
+            if self.pop_out {
+                return Err("PopOut needs gravity".into());
+            }
+            if self.is_networked() {
+                return Err("Games without gravity can not be played over the network".into());
+            }
+            // Scripts list columns, without gravity a move needs a cell
+            if self
+                .seats
+                .iter()
+                .any(|seat| matches!(seat, PlayerKind::Script(_)))
+            {
+                return Err("Scripted seats need gravity".into());
+            }
+        }
+        let remote_seats = self.remote_seats().len();
+        match &self.network {
+            Some(Network::Join { .. } | Network::Server { .. } | Network::Spectate { .. })
+                if self.load.is_some() =>
+            {
+                return Err("A joined game can not be loaded from a file".into())
+            }
+            // The host decides the rules and seats once we joined
+            Some(Network::Join { .. } | Network::Server { .. } | Network::Spectate { .. }) => {
+                return Ok(())
+            }
+            Some(Network::Host(_)) if remote_seats == 0 => {
+                return Err("Hosting needs at least one remote seat in '--seats'".into())
+            }
+            None if remote_seats > 0 => {
+                return Err("Remote seats need a hosted game, see '--host'".into())
+            }
+            _ => {}
+        }
+        if self.seats.len() != self.player_count as usize {
+            return Err(format!(
+                "Got {} seats for {} players",
+                self.seats.len(),
+                self.player_count
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Takes over the rules of a loaded game, new seats are played by humans.
+    pub fn adopt_rules(&mut self, game: &Game) {
+        self.player_count = game.player_count();
+        self.connect_size = game.connect_size();
+        self.board_width = game.width();
+        self.board_height = game.board().height();
+        self.board_depth = game.depth();
+        self.pop_out = game.pop_out();
+        self.gravity = game.gravity();
+        self.topology = game.topology();
+        self.blocked = game.board().blocked_cells();
+        self.seats
+            .resize(self.player_count as usize, PlayerKind::Human);
+    }
+
+    pub fn start_game(&self) -> Res<Game> {
+        match &self.load {
+            Some(path) => Game::load(path),
+            None => Ok(self.new_game()),
+        }
+    }
+
+    pub fn new_game(&self) -> Game {
+        Game::new(
+            self.player_count,
+            self.connect_size,
+            self.board_width,
+            self.board_height,
+        )
+        .with_depth(self.board_depth)
+        .with_pop_out(self.pop_out)
+        .with_gravity(self.gravity)
+        .with_topology(self.topology)
+        .with_blocked_cells(&self.blocked)
+    }
+
+    /// Whether a human plays this seat here. Seats of a joined game that belong to somebody else
+    /// are not.
+    pub fn is_human(&self, player: u32) -> bool {
+        match &self.network {
+            Some(Network::Join { seat, .. } | Network::Server { seat, .. })
+                if *seat != Some(player) =>
+            {
+                false
+            }
+            Some(Network::Spectate { .. }) => false,
+            _ => self.seats.get(player as usize) == Some(&PlayerKind::Human),
+        }
+    }
+
+    /// The name `seat` is rated under.
+    pub fn seat_name(&self, seat: u32) -> String {
+        match self.names.get(seat as usize) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => self.seats[seat as usize].to_string(),
+        }
+    }
+
+    /// Updates the rating file after a local game ended. Network games are not rated, as the
+    /// other seats are not known here.
+    pub fn rate(&self, game: &Game) -> Res<()> {
+        if self.is_networked() || !game.is_over() {
+            return Ok(());
+        }
+        let names: Vec<String> = (0..self.player_count).map(|i| self.seat_name(i)).collect();
+        let mut ratings = Ratings::load(&self.ratings)?;
+        ratings.record(game, &names)?;
+        ratings.save(&self.ratings)
+    }
+
+    /// Network games can not be restarted or have their moves taken back.
+    pub fn is_networked(&self) -> bool {
+        self.network.is_some()
+    }
+
+    fn remote_seats(&self) -> Vec<u32> {
+        (0..self.seats.len() as u32)
+            .filter(|&i| self.seats[i as usize] == PlayerKind::Remote)
+            .collect()
+    }
+
+    /// Creates the first game and its players. Network games are connected here, which blocks
+    /// until every seat is taken.
+    pub fn start(
+        &mut self,
+        human: &mut dyn FnMut() -> Box<dyn Player>,
+    ) -> Res<(Game, Vec<Box<dyn Player>>)> {
+        match self.network.clone() {
+            None => Ok((self.start_game()?, self.create_players(human)?)),
+            Some(Network::Host(address)) => {
+                let game = self.start_game()?;
+                let remote_seats = self.remote_seats();
+                println!(
+                    "Waiting on {} for {} player(s) to join",
+                    address,
+                    remote_seats.len()
+                );
+                let mut remotes = net::host(&address, &game, &remote_seats)?.into_iter();
+                let players = (0..self.player_count)
+                    .map(|i| match self.seats[i as usize] {
+                        PlayerKind::Remote => remotes
+                            .next()
+                            .map(|remote| Box::new(remote) as Box<dyn Player>)
+                            .ok_or_else(|| "A remote seat was not taken".into()),
+                        _ => self.create_player(i, human),
+                    })
+                    .collect::<Res<Vec<Box<dyn Player>>>>()?;
+                Ok((game, players))
+            }
+            Some(Network::Join { address, seat }) => {
+                let (game, seat, connection) = net::join(&address, seat)?;
+                println!("Joined the game on {} as player {}", address, seat + 1);
+                self.network = Some(Network::Join {
+                    address,
+                    seat: Some(seat),
+                });
+                self.seat_players(game, seat, connection, human)
+            }
+            Some(Network::Server {
+                address,
+                lobby,
+                seat,
+                token,
+            }) => {
+                let lobby = match lobby {
+                    Some(lobby) => lobby,
+                    None => {
+                        let lobby = net::create_lobby(
+                            &address,
+                            self.player_count,
+                            self.connect_size,
+                            self.board_width,
+                            self.board_height,
+                        )?;
+                        println!(
+                            "Created lobby {}, others can enter it with '--server {} --lobby {}'",
+                            lobby, address, lobby
+                        );
+                        lobby
+                    }
+                };
+                let (game, seat, connection, token) = match token {
+                    Some(token) => net::rejoin_lobby(&address, lobby, token)?,
+                    None => {
+                        println!("Waiting in lobby {} until every seat is taken", lobby);
+                        net::enter_lobby(&address, lobby, seat)?
+                    }
+                };
+                println!("Playing in lobby {} as player {}", lobby, seat + 1);
+                println!(
+                    "If the connection breaks, rejoin with '--server {} --lobby {} --rejoin {}'",
+                    address, lobby, token
+                );
+                self.network = Some(Network::Server {
+                    address,
+                    lobby: Some(lobby),
+                    seat: Some(seat),
+                    token: Some(token),
+                });
+                self.seat_players(game, seat, connection, human)
+            }
+            Some(Network::Spectate { address, lobby }) => {
+                let (game, connection) = net::spectate(&address, lobby)?;
+                println!("Watching lobby {}", lobby);
+                self.adopt_rules(&game);
+                let players = (0..self.player_count)
+                    .map(|i| Box::new(HostFeed::new(connection.clone(), i)) as Box<dyn Player>)
+                    .collect();
+                Ok((game, players))
+            }
+        }
+    }
+
+    /// The players of a joined game: `seat` is played here, all others come from the host.
+    fn seat_players(
+        &mut self,
+        game: Game,
+        seat: u32,
+        connection: Rc<Connection>,
+        human: &mut dyn FnMut() -> Box<dyn Player>,
+    ) -> Res<(Game, Vec<Box<dyn Player>>)> {
+        self.adopt_rules(&game);
+        let players = (0..self.player_count)
+            .map(|i| -> Res<Box<dyn Player>> {
+                if i == seat {
+                    let player = self.create_player(i, human)?;
+                    Ok(Box::new(Relay::new(player, connection.clone(), seat)))
+                } else {
+                    Ok(Box::new(HostFeed::new(connection.clone(), i)))
+                }
+            })
+            .collect::<Res<Vec<Box<dyn Player>>>>()?;
+        Ok((game, players))
+    }
+
+    /// One player per seat, `human` creates the ones for the seats that humans play.
+    pub fn create_players(
+        &self,
+        human: &mut dyn FnMut() -> Box<dyn Player>,
+    ) -> Res<Vec<Box<dyn Player>>> {
+        (0..self.player_count)
+            .map(|i| self.create_player(i, human))
+            .collect()
+    }
+
+    fn create_player(
+        &self,
+        seat: u32,
+        human: &mut dyn FnMut() -> Box<dyn Player>,
+    ) -> Res<Box<dyn Player>> {
+        Ok(match &self.seats[seat as usize] {
+            PlayerKind::Human => human(),
+            PlayerKind::Minimax(depth) => Box::new(Ai::minimax(*depth)),
+            PlayerKind::Mcts(budget) => {
+                Box::new(Ai::mcts(*budget, self.seed.wrapping_add(seat as u64)))
+            }
+            PlayerKind::Script(columns) => Box::new(ScriptedPlayer::new(columns.clone())),
+            PlayerKind::Engine(command, time) => {
+                let mut words = command.split_whitespace();
+                let program = words.next().unwrap_or_default();
+                let args: Vec<&str> = words.collect();
+                Box::new(EngineProcess::spawn(
+                    program,
+                    &args,
+                    Limit::MoveTime(*time),
+                )?)
+            }
+            PlayerKind::Remote => {
+                return Err(format!("Seat {} can only be played over the network", seat + 1).into())
+            }
+        })
+    }
+}
+
+pub fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Res<T> {
+    value
+        .parse::<T>()
+        .map_err(|_| format!("Invalid value '{}' for '{}'", value, flag).into())
+}
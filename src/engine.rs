@@ -0,0 +1,427 @@
+//! A UCI style text protocol for bots, spoken over stdin and stdout. The crate can be the engine
+//! ([`serve`]) or drive an engine in another process ([`EngineProcess`]).
+//!
+//! Columns are counted from 1 and the moves use the notation of saved games ("4453", pops as
+//! "p4", boards wider than 9 columns separate the moves with spaces, games without gravity write
+//! every move as the cell "x,y"):
+//!
+//! | to the engine                           | from the engine                         |
+//! |-----------------------------------------|-----------------------------------------|
+//! | `newgame <width> <height> <connect> <players> [variants]` |                      |
+//! | `isready`                               | `readyok`                               |
+//! | `position moves <moves>`                |                                         |
+//! | `go [movetime <millis> \| depth <n> \| playouts <n>]` | `bestmove <move>`         |
+//! | `quit`                                  |                                         |
+//!
+//! The variants of `newgame` are `popout` or `free`, `cylinder` or `torus` and last `blocked`
+//! with the blocked cells as `x,y`, counted from 1 like the columns. 3D boards send their width
+//! as `<width>x<depth>` like saved games do, their moves are the numbers of the pillars.
+//!
+//! An engine answers a command it can not follow with `error <message>`. Other lines, like
+//! `info ...`, are ignored.
+
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command as Process, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ai::{mcts::Budget, Ai},
+    notation::{format_cells, format_moves, format_width, parse_actions, parse_cell, parse_width},
+    validate_rules_3d, Action, BoardBackend, Game, Player, Res, Topology,
+};
+
+/// How long the engine may think about a move.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Limit {
+    /// Search for this long, with `Mcts`.
+    MoveTime(Duration),
+    /// Search this many moves ahead, with `Minimax`.
+    Depth(usize),
+    /// Play this many random games, with `Mcts`.
+    Playouts(usize),
+}
+impl Default for Limit {
+    fn default() -> Self {
+        Limit::MoveTime(Duration::from_millis(1000))
+    }
+}
+impl Limit {
+    /// How long to wait for the `bestmove` of a search within this limit.
+    fn deadline(self) -> Duration {
+        match self {
+            Limit::MoveTime(time) => time + MOVE_GRACE,
+            Limit::Depth(_) | Limit::Playouts(_) => SEARCH_TIMEOUT,
+        }
+    }
+}
+
+/// How long an engine may take to answer `isready`.
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time an engine gets on top of `movetime` to send its move.
+const MOVE_GRACE: Duration = Duration::from_secs(2);
+/// `go depth` and `go playouts` have no time limit of their own, this is how long they may take.
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long an engine gets to exit after `quit` before it is killed.
+const QUIT_GRACE: Duration = Duration::from_secs(1);
+
+/// One line sent to the engine, see the module docs.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    /// `newgame`, starts a game with these rules.
+    NewGame {
+        /// Width of the board, of a layer for a 3D board.
+        width: usize,
+        /// Height of the board.
+        height: usize,
+        /// 1 unless the board is three dimensional.
+        depth: usize,
+        /// Pieces in a row needed to win.
+        connect_size: usize,
+        /// Number of players.
+        player_count: u32,
+        /// Whether the `popout` variant is played.
+        pop_out: bool,
+        /// False for the `free` variant, where pieces go on any empty cell.
+        gravity: bool,
+        /// `cylinder` or `torus`, flat if neither is given.
+        topology: Topology,
+        /// Cells counted from 0, like the columns of `Position` and `bestmove` once read.
+        blocked: Vec<(usize, usize)>,
+    },
+    /// `isready`, answered with `readyok` once the engine can take commands.
+    IsReady,
+    /// The moves played since `newgame`, as written after `moves`.
+    Position(String),
+    /// `go`, asks for the best move within the limit.
+    Go(Limit),
+    /// `quit`, ends the engine.
+    Quit,
+}
+impl Command {
+    /// Reads a line of the protocol.
+    pub fn parse(line: &str) -> Res<Self> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |word: &str| -> Res<usize> {
+            word.parse()
+                .map_err(|_| format!("Invalid number '{}'", word).into())
+        };
+        match words[..] {
+            ["newgame", width, height, connect_size, player_count, ref variants @ ..] => {
+                let mut pop_out = false;
+                let mut gravity = true;
+                let mut topology = Topology::Flat;
+                let mut blocked = Vec::new();
+                let mut variants = variants.iter();
+                while let Some(&variant) = variants.next() {
+                    match (variant, Topology::parse(variant)) {
+                        ("popout", _) => pop_out = true,
+                        ("free", _) => gravity = false,
+                        // The cells are the rest of the line
+                        ("blocked", _) => {
+                            blocked = variants
+                                .by_ref()
+                                .map(|&cell| {
+                                    parse_cell(cell)
+                                        .ok_or_else(|| format!("Invalid cell '{}'", cell).into())
+                                })
+                                .collect::<Res<Vec<(usize, usize)>>>()?
+                        }
+                        (_, Some(wrapped)) => topology = wrapped,
+                        _ => return Err(format!("Unknown variant '{}'", variant).into()),
+                    }
+                }
+                let (width, depth) =
+                    parse_width(width).ok_or_else(|| format!("Invalid width '{}'", width))?;
+                Ok(Command::NewGame {
+                    width,
+                    height: number(height)?,
+                    depth,
+                    connect_size: number(connect_size)?,
+                    player_count: u32::try_from(number(player_count)?)?,
+                    pop_out,
+                    gravity,
+                    topology,
+                    blocked,
+                })
+            }
+            ["isready"] => Ok(Command::IsReady),
+            ["position", "moves", ..] => Ok(Command::Position(words[2..].join(" "))),
+            ["position"] => Ok(Command::Position(String::new())),
+            ["go"] => Ok(Command::Go(Limit::default())),
+            ["go", "movetime", millis] => Ok(Command::Go(Limit::MoveTime(Duration::from_millis(
+                number(millis)? as u64,
+            )))),
+            ["go", "depth", depth] => Ok(Command::Go(Limit::Depth(number(depth)?))),
+            ["go", "playouts", playouts] => Ok(Command::Go(Limit::Playouts(number(playouts)?))),
+            ["quit"] => Ok(Command::Quit),
+            _ => Err(format!("Unknown command '{}'", line).into()),
+        }
+    }
+}
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::NewGame {
+                width,
+                height,
+                depth,
+                connect_size,
+                player_count,
+                pop_out,
+                gravity,
+                topology,
+                blocked,
+            } => {
+                write!(
+                    f,
+                    "newgame {} {} {} {}",
+                    format_width(*width, *depth),
+                    height,
+                    connect_size,
+                    player_count
+                )?;
+                if !gravity {
+                    write!(f, " free")?;
+                } else if *pop_out {
+                    write!(f, " popout")?;
+                }
+                if *topology != Topology::Flat {
+                    write!(f, " {}", topology)?;
+                }
+                if !blocked.is_empty() {
+                    write!(f, " blocked {}", format_cells(blocked))?;
+                }
+                Ok(())
+            }
+            Command::IsReady => write!(f, "isready"),
+            Command::Position(moves) if moves.is_empty() => write!(f, "position"),
+            Command::Position(moves) => write!(f, "position moves {}", moves),
+            Command::Go(Limit::MoveTime(time)) => write!(f, "go movetime {}", time.as_millis()),
+            Command::Go(Limit::Depth(depth)) => write!(f, "go depth {}", depth),
+            Command::Go(Limit::Playouts(playouts)) => write!(f, "go playouts {}", playouts),
+            Command::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+/// Runs the built in AI as an engine, until `quit` or the end of the input. `go movetime` and
+/// `go playouts` search with MCTS, `go depth` with minimax.
+pub fn serve(input: impl BufRead, mut output: impl Write, seed: u64) -> Res<()> {
+    let mut game: Option<Game> = None;
+    let mut searches = 0;
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match Command::parse(&line) {
+            Ok(Command::NewGame {
+                width,
+                height,
+                depth,
+                connect_size,
+                player_count,
+                pop_out,
+                gravity,
+                topology,
+                blocked,
+            }) => match validate_rules_3d(player_count, connect_size, width, height, depth) {
+                Ok(()) => {
+                    game = Some(
+                        Game::new(player_count, connect_size, width, height)
+                            .with_depth(depth)
+                            .with_pop_out(pop_out)
+                            .with_gravity(gravity)
+                            .with_topology(topology)
+                            .with_blocked_cells(&blocked),
+                    );
+                    continue;
+                }
+                Err(e) => format!("error {}", e),
+            },
+            Ok(Command::IsReady) => "readyok".to_string(),
+            Ok(Command::Position(moves)) => match &mut game {
+                Some(game) => {
+                    let mut position = Game::new(
+                        game.player_count,
+                        game.connect_size,
+                        game.width(),
+                        game.board.height,
+                    )
+                    .with_depth(game.depth)
+                    .with_pop_out(game.pop_out)
+                    .with_gravity(game.gravity)
+                    .with_topology(game.topology)
+                    .with_blocked_cells(&game.board.blocked_cells());
+                    match parse_actions(&moves, position.board.width)
+                        .and_then(|actions| position.replay_actions(&actions))
+                    {
+                        Ok(()) => {
+                            *game = position;
+                            continue;
+                        }
+                        Err(e) => format!("error {}", e),
+                    }
+                }
+                None => "error There is no game, send 'newgame' first".to_string(),
+            },
+            Ok(Command::Go(limit)) => match &game {
+                Some(game) => {
+                    searches += 1;
+                    let mut ai = match limit {
+                        Limit::MoveTime(time) => {
+                            Ai::mcts(Budget::Time(time), seed.wrapping_add(searches))
+                        }
+                        Limit::Depth(depth) => Ai::minimax(depth.max(1)),
+                        Limit::Playouts(n) => {
+                            Ai::mcts(Budget::Playouts(n), seed.wrapping_add(searches))
+                        }
+                    };
+                    match ai.choose_action(game) {
+                        Ok(Action::Pop(x)) => format!("bestmove p{}", x + 1),
+                        Ok(Action::Place(x)) => format!("bestmove {}", x + 1),
+                        Ok(Action::PlaceAt(x, y)) => format!("bestmove {},{}", x + 1, y + 1),
+                        Err(e) => format!("error {}", e),
+                    }
+                }
+                None => "error There is no game, send 'newgame' first".to_string(),
+            },
+            Ok(Command::Quit) => return Ok(()),
+            Err(e) => format!("error {}", e),
+        };
+        writeln!(output, "{}", reply)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// A player whose moves come from an engine running in another process.
+pub struct EngineProcess {
+    child: Child,
+    input: Option<ChildStdin>,
+    /// Lines of the engine's output, read on another thread so waiting for them can time out.
+    output: Receiver<String>,
+    limit: Limit,
+    /// The last `newgame`, so it is only sent again when the rules change.
+    new_game: Option<Command>,
+}
+impl EngineProcess {
+    /// Starts `program` with `args` and asks it for moves within `limit`.
+    pub fn spawn(program: &str, args: &[&str], limit: Limit) -> Res<Self> {
+        let mut child = Process::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Could not start the engine '{}': {}", program, e))?;
+        let input = child.stdin.take();
+        let stdout = child.stdout.take().ok_or("The engine has no output")?;
+        let (sender, output) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(EngineProcess {
+            child,
+            input,
+            output,
+            limit,
+            new_game: None,
+        })
+    }
+
+    fn send(&mut self, command: &Command) -> Res<()> {
+        let input = self.input.as_mut().ok_or("The engine was stopped")?;
+        writeln!(input, "{}", command)?;
+        input.flush()?;
+        Ok(())
+    }
+
+    /// Reads lines until one starts with `keyword`, and returns the rest of it. An engine that
+    /// does not send it within `timeout` is stopped.
+    fn expect(&mut self, keyword: &str, timeout: Duration) -> Res<String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = match self
+                .output
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    let _ = self.child.kill();
+                    return Err(format!(
+                        "The engine did not send '{}' within {} ms",
+                        keyword,
+                        timeout.as_millis()
+                    )
+                    .into());
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err("The engine quit".into()),
+            };
+            let (word, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            if word == keyword {
+                return Ok(rest.to_string());
+            } else if word == "error" {
+                return Err(format!("The engine reported an error: {}", rest).into());
+            }
+        }
+    }
+}
+impl Player for EngineProcess {
+    fn choose_action(&mut self, game: &Game) -> Res<Action> {
+        let new_game = Command::NewGame {
+            width: game.width(),
+            height: game.board().height(),
+            depth: game.depth(),
+            connect_size: game.connect_size(),
+            player_count: game.player_count(),
+            pop_out: game.pop_out(),
+            gravity: game.gravity(),
+            topology: game.topology(),
+            blocked: game.board().blocked_cells(),
+        };
+        if self.new_game.as_ref() != Some(&new_game) {
+            self.send(&new_game)?;
+            self.send(&Command::IsReady)?;
+            self.expect("readyok", READY_TIMEOUT)?;
+            self.new_game = Some(new_game);
+        }
+        self.send(&Command::Position(format_moves(
+            game.history(),
+            game.board().width(),
+            game.gravity(),
+        )))?;
+        self.send(&Command::Go(self.limit))?;
+        let best = self.expect("bestmove", self.limit.deadline())?;
+        // A single move, so wide boards are read the same way
+        match parse_actions(best.trim(), usize::MAX)?[..] {
+            [action] => Ok(action),
+            _ => Err(format!("The engine sent an invalid move '{}'", best).into()),
+        }
+    }
+}
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        let _ = self.send(&Command::Quit);
+        // Closing its input also stops engines that ignore `quit`
+        self.input = None;
+        let deadline = Instant::now() + QUIT_GRACE;
+        while Instant::now() < deadline {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        // Engines that keep running are killed, so dropping a player never hangs
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,91 @@
+use std::{error::Error, fmt::Display};
+
+/// Everything the rules can reject. Front ends and bots can match on it instead of parsing
+/// messages, it still converts into the `Box<dyn Error>` of `Res`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum GameError {
+    /// The column has no empty cell left.
+    ColumnFull(usize),
+    /// The column is not on the board, `width` is the number of columns.
+    ColumnOutOfRange {
+        /// The column that was asked for.
+        column: usize,
+        /// Number of columns.
+        width: usize,
+    },
+    /// The bottom piece of the column is missing or belongs to another player.
+    CanNotPop(usize),
+    /// Pieces can only be popped out in the PopOut variant.
+    PopOutDisabled,
+    /// The row is not on the board, `height` is the number of rows.
+    RowOutOfRange {
+        /// The row that was asked for.
+        row: usize,
+        /// Number of rows.
+        height: usize,
+    },
+    /// There already is a piece on the cell.
+    CellOccupied {
+        /// Column of the cell.
+        x: usize,
+        /// Row of the cell.
+        y: usize,
+    },
+    /// The cell is blocked, no piece can ever go there.
+    CellBlocked {
+        /// Column of the cell.
+        x: usize,
+        /// Row of the cell.
+        y: usize,
+    },
+    /// Pieces fall down their column, they can not be put on a cell of choice.
+    GravityEnabled,
+    /// Without gravity a piece needs a cell, not just a column.
+    GravityDisabled,
+    /// Somebody already won or the board is full.
+    GameAlreadyOver,
+    /// There is no player with this index.
+    UnknownPlayer(u32),
+    /// The rules of a game can not be played, e.g. a line longer than the board.
+    InvalidConfig(String),
+}
+impl Display for GameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ColumnFull(column) => write!(f, "Column {} is already full", column),
+            Self::ColumnOutOfRange { column, width } => write!(
+                f,
+                "Column {} is not on the board, the columns go from 0 to {}",
+                column,
+                width.saturating_sub(1)
+            ),
+            Self::CanNotPop(column) => {
+                write!(
+                    f,
+                    "The bottom piece of column {} is not yours to pop",
+                    column
+                )
+            }
+            Self::PopOutDisabled => write!(f, "Pieces can only be popped out in PopOut games"),
+            Self::RowOutOfRange { row, height } => write!(
+                f,
+                "Row {} is not on the board, the rows go from 0 to {}",
+                row,
+                height.saturating_sub(1)
+            ),
+            Self::CellOccupied { x, y } => write!(f, "There already is a piece at {}, {}", x, y),
+            Self::CellBlocked { x, y } => write!(f, "The cell at {}, {} is blocked", x, y),
+            Self::GravityEnabled => write!(f, "Pieces fall down, pick a column instead of a cell"),
+            Self::GravityDisabled => {
+                write!(
+                    f,
+                    "There is no gravity in this game, pick a cell instead of a column"
+                )
+            }
+            Self::GameAlreadyOver => write!(f, "The game is already over"),
+            Self::UnknownPlayer(player) => write!(f, "There is no player {}", player + 1),
+            Self::InvalidConfig(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+impl Error for GameError {}
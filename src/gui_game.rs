@@ -1,13 +1,24 @@
-use crate::{gui_game::color_transform::hsva_to_rgba, take_turn, Game, Res};
+use crate::{
+    config::GameConfig,
+    gui_game::{
+        color_transform::hsva_to_rgba,
+        font::{draw_text, text_width, GLYPH_HEIGHT},
+    },
+};
+use connect_n::{
+    net::SeatEvent, play_turn, Action, BoardBackend, Game, GameError, MoveKind, Player, Res,
+    TurnOutcome,
+};
 
-use std::time::Instant;
+use std::{cell::Cell, rc::Rc, time::Instant};
 
 mod color_transform;
+mod font;
 
 use pixels::{Pixels, SurfaceTexture};
 use winit::{
     dpi::PhysicalSize,
-    event::{ElementState, Event, MouseButton, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
     event_loop::EventLoop,
     window::WindowBuilder,
 };
@@ -15,9 +26,146 @@ use winit::{
 const BACKGROUND_COLOR: [u8; 4] = [200, 200, 200, 255];
 const FOREGROUND_COLOR: [u8; 4] = [20, 20, 200, 255];
 const FOREGROUND_HIGHLIGHT_COLOR: [u8; 4] = [80, 120, 255, 255];
+const BLOCKED_COLOR: [u8; 4] = [70, 70, 90, 255];
+const WINNING_OUTLINE_COLOR: [u8; 4] = [255, 255, 255, 255];
+const OVERLAY_COLOR: [u8; 4] = [40, 40, 40, 255];
+const TEXT_COLOR: [u8; 4] = [255, 255, 255, 255];
+/// Acceleration of a dropped piece in grid cells per second squared.
+const GRAVITY: f64 = 40.0;
+
+/// A human clicking into the window. All human seats share the column of the last click, it is
+/// played by whichever of them is active. A left click places a piece, a right click pops one.
+/// Without gravity the click picks the cell under the mouse.
+struct GuiHuman {
+    click: Rc<Cell<Option<Action>>>,
+}
+impl Player for GuiHuman {
+    fn choose_action(&mut self, _game: &Game) -> Res<Action> {
+        self.click
+            .take()
+            .ok_or_else(|| "Nobody clicked on a column yet".into())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.click.get().is_some()
+    }
+}
+
+fn human(click: &Rc<Cell<Option<Action>>>) -> impl FnMut() -> Box<dyn Player> + '_ {
+    || {
+        Box::new(GuiHuman {
+            click: click.clone(),
+        })
+    }
+}
+
+struct FallingPiece {
+    x: u32,
+    y: u32,
+    color: [u8; 4],
+    outcome: TurnOutcome,
+    start: Instant,
+    drawn_at: Option<(i32, i32)>,
+}
+impl FallingPiece {
+    fn new(game: &Game, outcome: TurnOutcome, player_colors: &[[u8; 4]]) -> Option<Self> {
+        let last_move = game.history().last()?;
+        Some(FallingPiece {
+            x: last_move.x as u32,
+            y: last_move.y as u32,
+            color: player_colors[last_move.player as usize],
+            outcome,
+            start: Instant::now(),
+            drawn_at: None,
+        })
+    }
+
+    /// Center of the piece in pixels, `None` once it has reached its row.
+    fn position(
+        &self,
+        game: &Game,
+        grid_size: u32,
+        border_width: u32,
+        border_height: u32,
+    ) -> Option<(i32, i32)> {
+        let rows = (game.board().height() as u32 - self.y) as f64;
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let fallen = 0.5 * GRAVITY * elapsed * elapsed;
+        if fallen >= rows {
+            return None;
+        }
+        let center_x = (self.x * grid_size + border_width + grid_size / 2) as i32;
+        let center_y = (border_height + grid_size / 2) as i32 + (fallen * grid_size as f64) as i32;
+        Some((center_x, center_y))
+    }
+}
+
+/// Whether the last piece dropped down its column. A pop moves the whole column at once and
+/// without gravity pieces appear where they were put, so neither is animated. Neither are the
+/// pillars of a 3D board, they are seen from above.
+fn last_move_fell(game: &Game) -> bool {
+    game.gravity()
+        && game.depth() == 1
+        && game
+            .history()
+            .last()
+            .is_some_and(|last_move| last_move.kind == MoveKind::Place)
+}
+
+fn restore_piece_area(
+    frame: &mut [u8],
+    background_buffer: &[u8],
+    (center_x, center_y): (i32, i32),
+    radius: i32,
+    size: PhysicalSize<u32>,
+) {
+    let radius_square = radius.pow(2);
+    for x_offset in -radius..=radius {
+        let x = center_x + x_offset;
+        if x < 0 || x >= size.width as i32 {
+            continue;
+        }
+        let height = ((radius_square - x_offset.pow(2)) as f64).sqrt() as i32;
+        for y_offset in -height..=height {
+            let y = center_y + y_offset;
+            if y < 0 || y >= size.height as i32 {
+                continue;
+            }
+
+            let index = ((size.width * y as u32 + x as u32) * 4) as usize;
+            frame[index..index + 4].copy_from_slice(&background_buffer[index..index + 4]);
+        }
+    }
+}
+
+fn draw_piece(
+    frame: &mut [u8],
+    color: [u8; 4],
+    (center_x, center_y): (i32, i32),
+    radius: i32,
+    size: PhysicalSize<u32>,
+) {
+    let radius_square = radius.pow(2);
+    for x_offset in -radius..=radius {
+        let x = center_x + x_offset;
+        if x < 0 || x >= size.width as i32 {
+            continue;
+        }
+        let height = ((radius_square - x_offset.pow(2)) as f64).sqrt() as i32;
+        for y_offset in -height..=height {
+            let y = center_y + y_offset;
+            if y < 0 || y >= size.height as i32 {
+                continue;
+            }
+
+            let index = ((size.width * y as u32 + x as u32) * 4) as usize;
+            frame[index..index + 4].copy_from_slice(&color);
+        }
+    }
+}
 
 fn create_cell(
-    buffer: &mut Vec<u8>,
+    buffer: &mut [u8],
     player_color: Option<[u8; 4]>,
     foreground_color: [u8; 4],
     x_offset: u32,
@@ -51,6 +199,310 @@ fn create_cell(
     }
 }
 
+/// Top left pixel of the board cell (x, y). A 3D board gets one panel per layer, from the bottom
+/// one on the left to the top one on the right. The panels show the layer from above with the
+/// front row of pillars at the bottom.
+fn cell_offset(
+    game: &Game,
+    x: u32,
+    y: u32,
+    grid_size: u32,
+    border_width: u32,
+    border_height: u32,
+) -> (u32, u32) {
+    let (screen_column, screen_row) = if game.depth() == 1 {
+        (x, game.board().height() as u32 - y)
+    } else {
+        let (pillar_x, pillar_z) = game.pillar_position(x as usize);
+        let width = game.width() as u32;
+        (
+            y * (width + 1) + pillar_x as u32,
+            game.depth() as u32 - pillar_z as u32,
+        )
+    };
+    (
+        screen_column * grid_size + border_width,
+        screen_row * grid_size + border_height,
+    )
+}
+
+/// The colors of the piece and the frame of the board cell (x, y), `None` outside of the board.
+/// Blocked cells are a solid block without a hole.
+fn cell_colors(
+    game: &Game,
+    player_colors: &[[u8; 4]],
+    x: u32,
+    y: u32,
+    foreground_color: [u8; 4],
+) -> Option<(Option<[u8; 4]>, [u8; 4])> {
+    let cell = game.board().get_cell(x as usize, y as usize)?;
+    Some(if cell.is_blocked() {
+        (Some(BLOCKED_COLOR), BLOCKED_COLOR)
+    } else if cell.occupance() == 0 {
+        (None, foreground_color)
+    } else {
+        (
+            Some(player_colors[cell.occupance() as usize - 1]),
+            foreground_color,
+        )
+    })
+}
+
+/// Faded version of `color` for the cells beyond a wrapping edge.
+fn ghost(color: [u8; 4]) -> [u8; 4] {
+    let mut faded = color;
+    for (channel, background) in faded.iter_mut().zip(BACKGROUND_COLOR).take(3) {
+        *channel = ((*channel as u32 + 2 * background as u32) / 3) as u8;
+    }
+    faded
+}
+
+/// Repeats the edges that wrap around next to the board: the last column left of it, the first
+/// column right of it and on a torus the top row below it.
+fn draw_ghosts(
+    buffer: &mut [u8],
+    size: PhysicalSize<u32>,
+    game: &Game,
+    player_colors: &[[u8; 4]],
+    grid_size: u32,
+    border_width: u32,
+    border_height: u32,
+) {
+    let topology = game.topology();
+    let (width, height) = (game.board().width(), game.board().height());
+    let mut ghosts: Vec<(i64, i64)> = Vec::new();
+    if topology.wraps_x() {
+        for y in 0..height as i64 {
+            ghosts.extend([(-1, y), (width as i64, y)]);
+        }
+    }
+    if topology.wraps_y() {
+        ghosts.extend((0..width as i64).map(|x| (x, -1)));
+    }
+
+    for (x, y) in ghosts {
+        let Some((board_x, board_y)) = topology.wrap(x, y, width, height) else {
+            continue;
+        };
+        let Some((player_color, foreground_color)) = cell_colors(
+            game,
+            player_colors,
+            board_x as u32,
+            board_y as u32,
+            FOREGROUND_COLOR,
+        ) else {
+            continue;
+        };
+        create_cell(
+            buffer,
+            Some(ghost(player_color.unwrap_or(BACKGROUND_COLOR))),
+            ghost(foreground_color),
+            (border_width as i64 + x * grid_size as i64) as u32,
+            ((height as i64 - y) * grid_size as i64) as u32 + border_height,
+            grid_size,
+            size.width as usize,
+        );
+    }
+}
+
+fn draw_winning_line(
+    buffer: &mut [u8],
+    size: PhysicalSize<u32>,
+    game: &Game,
+    grid_size: u32,
+    border_width: u32,
+    border_height: u32,
+) {
+    let winning_cells = match game.winning_line() {
+        None => return,
+        Some(cells) => cells,
+    };
+
+    let center = (grid_size / 2) as i32;
+    let radius = calc_piece_radius(grid_size) as i32;
+    let inner_radius_square = radius.pow(2);
+    let outer_radius_square = (radius + (grid_size as i32 / 15).max(2)).pow(2);
+
+    for (x, y) in winning_cells {
+        let (x_offset, y_offset) = cell_offset(
+            game,
+            x as u32,
+            y as u32,
+            grid_size,
+            border_width,
+            border_height,
+        );
+        for x_pix in 0..grid_size {
+            let x_dist = (center - x_pix as i32).pow(2);
+            for y_pix in 0..grid_size {
+                let dist = x_dist + (center - y_pix as i32).pow(2);
+                if dist <= inner_radius_square || dist > outer_radius_square {
+                    continue;
+                }
+                let index = ((size.width * (y_pix + y_offset) + x_pix + x_offset) * 4) as usize;
+                buffer[index..index + 4].copy_from_slice(&WINNING_OUTLINE_COLOR);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+impl Rect {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x as i32
+            && y >= self.y as i32
+            && x < (self.x + self.width) as i32
+            && y < (self.y + self.height) as i32
+    }
+
+    fn fill(&self, buffer: &mut [u8], buffer_width: u32, color: [u8; 4]) {
+        for y in self.y..self.y + self.height {
+            for x in self.x..self.x + self.width {
+                let index = ((buffer_width * y + x) * 4) as usize;
+                buffer[index..index + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    /// Largest text scale at which `text` fits the width and stays below `max_text_height`.
+    fn fit_text_scale(&self, text: &str, max_text_height: u32) -> u32 {
+        (self.width * 9 / 10 / text_width(text, 1).max(1))
+            .min(max_text_height / GLYPH_HEIGHT)
+            .max(1)
+    }
+
+    fn draw_centered_text(
+        &self,
+        buffer: &mut [u8],
+        buffer_width: u32,
+        text: &str,
+        scale: u32,
+        color: [u8; 4],
+    ) {
+        let x = self.x + self.width.saturating_sub(text_width(text, scale)) / 2;
+        let y = self.y + self.height.saturating_sub(GLYPH_HEIGHT * scale) / 2;
+        draw_text(buffer, buffer_width, text, x, y, scale, color);
+    }
+}
+
+enum OverlayAction {
+    PlayAgain,
+    Quit,
+}
+
+/// Panel, "play again" button and "quit" button of the game over screen.
+fn calc_overlay_layout(size: PhysicalSize<u32>) -> (Rect, Rect, Rect) {
+    let panel = Rect {
+        x: size.width / 8,
+        y: size.height / 4,
+        width: size.width * 3 / 4,
+        height: size.height / 2,
+    };
+    let button_width = panel.width * 2 / 5;
+    let button_height = panel.height / 4;
+    let gap = (panel.width - 2 * button_width) / 3;
+    let button_y = panel.y + panel.height - button_height - panel.height / 8;
+    let play_again = Rect {
+        x: panel.x + gap,
+        y: button_y,
+        width: button_width,
+        height: button_height,
+    };
+    let quit = Rect {
+        x: panel.x + 2 * gap + button_width,
+        ..play_again
+    };
+    (panel, play_again, quit)
+}
+
+fn draw_game_over_overlay(
+    buffer: &mut [u8],
+    size: PhysicalSize<u32>,
+    game: &Game,
+    player_colors: &[[u8; 4]],
+) {
+    let (title, title_color) = match game.status() {
+        TurnOutcome::Continue => return,
+        TurnOutcome::Draw => ("DRAW!".to_string(), TEXT_COLOR),
+        TurnOutcome::Win(player) => (
+            format!("PLAYER {} WINS!", player + 1),
+            player_colors[player as usize],
+        ),
+    };
+    let (panel, play_again, quit) = calc_overlay_layout(size);
+
+    panel.fill(buffer, size.width, OVERLAY_COLOR);
+    let title_area = Rect {
+        height: panel.height / 2,
+        ..panel
+    };
+    let title_scale = title_area.fit_text_scale(&title, panel.height / 4);
+    title_area.draw_centered_text(buffer, size.width, &title, title_scale, title_color);
+
+    let buttons = [(play_again, "PLAY AGAIN"), (quit, "QUIT")];
+    let button_scale = buttons
+        .iter()
+        .map(|(button, label)| button.fit_text_scale(label, button.height / 2))
+        .min()
+        .unwrap_or(1);
+    for (button, label) in buttons {
+        button.fill(buffer, size.width, FOREGROUND_COLOR);
+        button.draw_centered_text(buffer, size.width, label, button_scale, TEXT_COLOR);
+    }
+}
+
+fn overlay_action(
+    event: &Event<()>,
+    size: PhysicalSize<u32>,
+    mouse_x: i32,
+    mouse_y: i32,
+) -> Option<OverlayAction> {
+    let (_, play_again, quit) = calc_overlay_layout(size);
+    match event {
+        Event::WindowEvent {
+            event:
+                WindowEvent::MouseInput {
+                    state: ElementState::Released,
+                    button: MouseButton::Left,
+                    ..
+                },
+            ..
+        } => {
+            if play_again.contains(mouse_x, mouse_y) {
+                Some(OverlayAction::PlayAgain)
+            } else if quit.contains(mouse_x, mouse_y) {
+                Some(OverlayAction::Quit)
+            } else {
+                None
+            }
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => match key {
+            VirtualKeyCode::Return => Some(OverlayAction::PlayAgain),
+            VirtualKeyCode::Escape | VirtualKeyCode::Q => Some(OverlayAction::Quit),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 fn create_background_buffer(
     size: PhysicalSize<u32>,
     game: &Game,
@@ -59,29 +511,25 @@ fn create_background_buffer(
     border_width: u32,
     border_height: u32,
 ) -> Vec<u8> {
-    let width = game.board.width as u32;
-    let height = game.board.height as u32 + 1;
+    let width = game.board().width() as u32;
+    let height = game.board().height() as u32;
 
     let mut buffer = vec![BACKGROUND_COLOR; (size.width * size.height) as usize].concat();
 
     for x in 0..width {
-        let x_offset = x * grid_size + border_width;
-        for y in 0..height - 1 {
-            let y_offset = (height - (y + 1)) * grid_size + border_height;
+        for y in 0..height {
+            let (x_offset, y_offset) =
+                cell_offset(game, x, y, grid_size, border_width, border_height);
 
-            let occupance = match game.board.get_cell(x as usize, y as usize) {
-                None => break,
-                Some(n) => n.occupance,
-            };
-            let player_color = if occupance == 0 {
-                None
-            } else {
-                Some(player_colors[occupance as usize - 1])
+            let Some((player_color, foreground_color)) =
+                cell_colors(game, player_colors, x, y, FOREGROUND_COLOR)
+            else {
+                break;
             };
             create_cell(
                 &mut buffer,
                 player_color,
-                FOREGROUND_COLOR,
+                foreground_color,
                 x_offset,
                 y_offset,
                 grid_size,
@@ -89,12 +537,31 @@ fn create_background_buffer(
             )
         }
     }
+    draw_ghosts(
+        &mut buffer,
+        size,
+        game,
+        player_colors,
+        grid_size,
+        border_width,
+        border_height,
+    );
+    draw_winning_line(
+        &mut buffer,
+        size,
+        game,
+        grid_size,
+        border_width,
+        border_height,
+    );
+    draw_game_over_overlay(&mut buffer, size, game, player_colors);
 
     buffer
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_background_buffer_column(
-    buffer: &mut Vec<u8>,
+    buffer: &mut [u8],
     old_x: u32,
     x: u32,
     size: PhysicalSize<u32>,
@@ -104,81 +571,260 @@ fn update_background_buffer_column(
     border_width: u32,
     border_height: u32,
 ) {
-    let height = game.board.height as u32 + 1;
+    let height = game.board().height() as u32;
 
-    let old_x_offset = old_x * grid_size + border_width;
-    for y in 0..height - 1 {
-        let y_offset = (height - (y + 1)) * grid_size + border_height;
+    for y in 0..height {
+        let (x_offset, y_offset) =
+            cell_offset(game, old_x, y, grid_size, border_width, border_height);
 
-        let occupance = match game.board.get_cell(old_x as usize, y as usize) {
-            None => break,
-            Some(n) => n.occupance,
-        };
-        let player_color = if occupance == 0 {
-            None
-        } else {
-            Some(player_colors[occupance as usize - 1])
+        let Some((player_color, foreground_color)) =
+            cell_colors(game, player_colors, old_x, y, FOREGROUND_COLOR)
+        else {
+            break;
         };
         create_cell(
             buffer,
             player_color,
-            FOREGROUND_COLOR,
-            old_x_offset,
+            foreground_color,
+            x_offset,
             y_offset,
             grid_size,
             size.width as usize,
         )
     }
-    let x_offset = x * grid_size + border_width;
-    for y in 0..height - 1 {
-        let y_offset = (height - (y + 1)) * grid_size + border_height;
+    for y in 0..height {
+        let (x_offset, y_offset) = cell_offset(game, x, y, grid_size, border_width, border_height);
 
-        let occupance = match game.board.get_cell(x as usize, y as usize) {
-            None => break,
-            Some(n) => n.occupance,
+        let Some((player_color, foreground_color)) =
+            cell_colors(game, player_colors, x, y, FOREGROUND_HIGHLIGHT_COLOR)
+        else {
+            break;
         };
-        let player_color = if occupance == 0 {
-            None
-        } else {
-            Some(player_colors[occupance as usize - 1])
+        create_cell(
+            buffer,
+            player_color,
+            foreground_color,
+            x_offset,
+            y_offset,
+            grid_size,
+            size.width as usize,
+        )
+    }
+    draw_ghosts(
+        buffer,
+        size,
+        game,
+        player_colors,
+        grid_size,
+        border_width,
+        border_height,
+    );
+    draw_winning_line(buffer, size, game, grid_size, border_width, border_height);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_background_buffer_cell(
+    buffer: &mut [u8],
+    (old_x, old_y): (u32, u32),
+    (x, y): (u32, u32),
+    size: PhysicalSize<u32>,
+    game: &Game,
+    player_colors: &[[u8; 4]],
+    grid_size: u32,
+    border_width: u32,
+    border_height: u32,
+) {
+    for (x, y, foreground_color) in [
+        (old_x, old_y, FOREGROUND_COLOR),
+        (x, y, FOREGROUND_HIGHLIGHT_COLOR),
+    ] {
+        let Some((player_color, foreground_color)) =
+            cell_colors(game, player_colors, x, y, foreground_color)
+        else {
+            continue;
         };
+        let (x_offset, y_offset) = cell_offset(game, x, y, grid_size, border_width, border_height);
         create_cell(
             buffer,
             player_color,
-            FOREGROUND_HIGHLIGHT_COLOR,
+            foreground_color,
             x_offset,
             y_offset,
             grid_size,
             size.width as usize,
         )
     }
+    draw_ghosts(
+        buffer,
+        size,
+        game,
+        player_colors,
+        grid_size,
+        border_width,
+        border_height,
+    );
+    draw_winning_line(buffer, size, game, grid_size, border_width, border_height);
+}
+
+/// Moves the hover highlight from the `old` to the `new` (column, row) under the mouse. With
+/// gravity the whole column lights up, without it just the cell.
+#[allow(clippy::too_many_arguments)]
+fn update_highlight(
+    buffer: &mut [u8],
+    old: (u32, u32),
+    new: (u32, u32),
+    size: PhysicalSize<u32>,
+    game: &Game,
+    player_colors: &[[u8; 4]],
+    grid_size: u32,
+    border_width: u32,
+    border_height: u32,
+) {
+    if game.gravity() {
+        update_background_buffer_column(
+            buffer,
+            old.0,
+            new.0,
+            size,
+            game,
+            player_colors,
+            grid_size,
+            border_width,
+            border_height,
+        );
+    } else {
+        update_background_buffer_cell(
+            buffer,
+            old,
+            new,
+            size,
+            game,
+            player_colors,
+            grid_size,
+            border_width,
+            border_height,
+        );
+    }
 }
 
 fn calc_grid_constants(game: &Game, size: &PhysicalSize<u32>) -> (u32, u32, u32) {
-    let width = game.board.width as u32;
-    let height = game.board.height as u32 + 1;
-    let grid_tile_width = (size.width as f64 / width as f64).floor() as u32;
-    let grid_tile_height = (size.height as f64 / height as f64).floor() as u32;
+    if game.depth() > 1 {
+        return calc_layer_grid_constants(game, size);
+    }
+    let width = game.board().width() as u32;
+    let height = game.board().height() as u32 + 1;
+    // Room for the ghosts of wrapping edges
+    let ghost_columns = if game.topology().wraps_x() { 2 } else { 0 };
+    let ghost_rows = if game.topology().wraps_y() { 1 } else { 0 };
+    let grid_tile_width = (size.width as f64 / (width + ghost_columns) as f64).floor() as u32;
+    let grid_tile_height = (size.height as f64 / (height + ghost_rows) as f64).floor() as u32;
     let grid_size = grid_tile_width.min(grid_tile_height);
 
     let border_width = (size.width - width * grid_size) / 2;
-    let border_height = (size.height - height * grid_size) / 2;
+    let border_height = (size.height - (height + ghost_rows) * grid_size) / 2;
+
+    (grid_size, border_width, border_height)
+}
+
+/// Like `calc_grid_constants` for the layer panels of a 3D board, with a free column between
+/// them and the row for the next piece on top.
+fn calc_layer_grid_constants(game: &Game, size: &PhysicalSize<u32>) -> (u32, u32, u32) {
+    let layers = game.board().height() as u32;
+    let columns = layers * (game.width() as u32 + 1) - 1;
+    let rows = game.depth() as u32 + 1;
+    let grid_size = (size.width / columns).min(size.height / rows);
+
+    let border_width = (size.width - columns * grid_size) / 2;
+    let border_height = (size.height - rows * grid_size) / 2;
 
     (grid_size, border_width, border_height)
 }
 
+/// Board column under the mouse. With `wraps` the ghost columns stand for the opposite edge.
+fn calc_mouse_column(
+    mouse_x: i32,
+    board_width: usize,
+    grid_size: u32,
+    border_width: u32,
+    wraps: bool,
+) -> u32 {
+    let column = (mouse_x - border_width as i32).div_euclid(grid_size as i32);
+    if wraps && (-1..=board_width as i32).contains(&column) {
+        column.rem_euclid(board_width as i32) as u32
+    } else {
+        column.clamp(0, board_width as i32 - 1) as u32
+    }
+}
+
+/// Board row under the mouse, the row above the board counts as the top one.
+fn calc_mouse_row(mouse_y: i32, board_height: usize, grid_size: u32, border_height: u32) -> u32 {
+    let screen_row = (mouse_y - border_height as i32) / grid_size as i32;
+    (board_height as i32 - screen_row).clamp(0, board_height as i32 - 1) as u32
+}
+
+/// Column of the pillar under the mouse on a 3D board, in whichever layer panel it is. The
+/// gap after a panel counts as its last row of pillars.
+fn calc_mouse_pillar(
+    game: &Game,
+    (mouse_x, mouse_y): (i32, i32),
+    grid_size: u32,
+    border_width: u32,
+    border_height: u32,
+) -> u32 {
+    let (width, depth) = (game.width() as i32, game.depth() as i32);
+    let screen_column = (mouse_x - border_width as i32).div_euclid(grid_size as i32);
+    let x = screen_column.rem_euclid(width + 1).min(width - 1);
+    let screen_row = (mouse_y - border_height as i32).div_euclid(grid_size as i32);
+    let z = (depth - screen_row).clamp(0, depth - 1);
+    game.pillar(x as usize, z as usize) as u32
+}
+
+/// Board (column, row) under the mouse, the row is 0 on a 3D board.
+fn calc_mouse_cell(
+    game: &Game,
+    (mouse_x, mouse_y): (i32, i32),
+    grid_size: u32,
+    border_width: u32,
+    border_height: u32,
+) -> (u32, u32) {
+    if game.depth() > 1 {
+        let pillar = calc_mouse_pillar(
+            game,
+            (mouse_x, mouse_y),
+            grid_size,
+            border_width,
+            border_height,
+        );
+        return (pillar, 0);
+    }
+    (
+        calc_mouse_column(
+            mouse_x,
+            game.board().width(),
+            grid_size,
+            border_width,
+            game.topology().wraps_x(),
+        ),
+        calc_mouse_row(mouse_y, game.board().height(), grid_size, border_height),
+    )
+}
+
 fn calc_piece_radius(grid_size: u32) -> u32 {
     grid_size * 6 / 10 / 2
 }
 
-pub fn gui_game(
-    player_count: u32,
-    connect_size: usize,
-    board_width: usize,
-    board_height: usize,
-) -> Res<()> {
-    let mut game = Game::new(player_count, connect_size, board_width, board_height);
-    let mut game_over = false;
+fn announce_outcome(outcome: TurnOutcome) {
+    match outcome {
+        TurnOutcome::Win(player) => println!("Player {} won!!", player + 1),
+        TurnOutcome::Draw => println!("The board is full. It's a draw!!"),
+        TurnOutcome::Continue => {}
+    }
+}
+
+pub fn gui_game(mut config: GameConfig) -> Res<()> {
+    let click = Rc::new(Cell::new(None));
+    let (mut game, mut players) = config.start(&mut human(&click))?;
+    let player_count = config.player_count;
 
     let mut player_colors: Vec<[u8; 4]> = Vec::with_capacity(player_count as usize);
     for i in 0..player_count {
@@ -209,13 +855,61 @@ pub fn gui_game(
     .unwrap();
 
     let mut mouse_x: i32 = 0;
-    let mut mouse_column: u32 = 0;
     let mut mouse_y: i32 = 0;
+    let mut mouse_column: u32 = 0;
+    let mut mouse_row: u32 = 0;
 
-    let (mut old_x, mut old_y) = (0, 0);
+    let mut old_x = 0;
+    let mut falling: Option<FallingPiece> = None;
     event_loop.run(move |event, _, control_flow| {
         control_flow.set_poll();
 
+        if game.is_over() && falling.is_none() {
+            match overlay_action(&event, size, mouse_x, mouse_y) {
+                Some(OverlayAction::Quit) => {
+                    control_flow.set_exit();
+                    return;
+                }
+                Some(OverlayAction::PlayAgain) if config.is_networked() => {
+                    println!("A network game can not be restarted");
+                }
+                Some(OverlayAction::PlayAgain) => {
+                    game = config.new_game();
+                    players = match config.create_players(&mut human(&click)) {
+                        Ok(players) => players,
+                        Err(e) => {
+                            println!("{}", e);
+                            control_flow.set_exit();
+                            return;
+                        }
+                    };
+                    click.set(None);
+                    background_buffer = create_background_buffer(
+                        size,
+                        &game,
+                        &player_colors,
+                        grid_size,
+                        border_width,
+                        border_height,
+                    );
+                    update_highlight(
+                        &mut background_buffer,
+                        (mouse_column, mouse_row),
+                        (mouse_column, mouse_row),
+                        size,
+                        &game,
+                        &player_colors,
+                        grid_size,
+                        border_width,
+                        border_height,
+                    );
+                    buffer.frame_mut().clone_from_slice(&background_buffer);
+                    return;
+                }
+                None => {}
+            }
+        }
+
         match event {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
@@ -241,25 +935,130 @@ pub fn gui_game(
                 buffer.resize_surface(size.width, size.height).unwrap();
                 buffer.resize_buffer(size.width, size.height).unwrap();
                 buffer.frame_mut().clone_from_slice(&background_buffer);
+                if let Some(piece) = &mut falling {
+                    piece.drawn_at = None;
+                }
             }
             Event::WindowEvent {
                 event:
                     WindowEvent::MouseInput {
                         state: ElementState::Released,
-                        button: MouseButton::Left,
+                        button: button @ (MouseButton::Left | MouseButton::Right),
                         ..
                     },
                 ..
+            } if !game.is_over() && falling.is_none() && config.is_human(game.active_player()) => {
+                click.set(Some(match button {
+                    _ if !game.gravity() => {
+                        Action::PlaceAt(mouse_column as usize, mouse_row as usize)
+                    }
+                    MouseButton::Right => Action::Pop(mouse_column as usize),
+                    _ => Action::Place(mouse_column as usize),
+                }));
+            }
+            // A finished game was rated already, replaying its end would rate it again
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key @ (VirtualKeyCode::U | VirtualKeyCode::R)),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if falling.is_none() && !game.is_over() && !config.is_networked() => {
+                let changed = if key == VirtualKeyCode::U {
+                    let changed = game.undo();
+                    while !config.is_human(game.active_player()) && game.undo().is_some() {}
+                    changed
+                } else {
+                    let changed = game.redo();
+                    while changed.is_some()
+                        && !config.is_human(game.active_player())
+                        && game.redo().is_some()
+                    {}
+                    changed
+                };
+                if changed.is_some() {
+                    background_buffer = create_background_buffer(
+                        size,
+                        &game,
+                        &player_colors,
+                        grid_size,
+                        border_width,
+                        border_height,
+                    );
+                    update_highlight(
+                        &mut background_buffer,
+                        (mouse_column, mouse_row),
+                        (mouse_column, mouse_row),
+                        size,
+                        &game,
+                        &player_colors,
+                        grid_size,
+                        border_width,
+                        border_height,
+                    );
+                    buffer.frame_mut().clone_from_slice(&background_buffer);
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
             } => {
-                if !game_over {
-                    match take_turn(&mut game, &mut |_: &Game| Ok(mouse_column as usize)) {
-                        Err(e) => println!("{}", e),
-                        Ok(s) => {
-                            game_over = s;
-                            update_background_buffer_column(
+                (mouse_x, mouse_y) = ((position.x).ceil() as i32, (position.y).ceil() as i32);
+                let new_mouse_cell = calc_mouse_cell(
+                    &game,
+                    (mouse_x, mouse_y),
+                    grid_size,
+                    border_width,
+                    border_height,
+                );
+                // The column under a falling piece is only repainted once it has landed
+                if (mouse_column, mouse_row) != new_mouse_cell
+                    && !game.is_over()
+                    && falling.is_none()
+                {
+                    update_highlight(
+                        &mut background_buffer,
+                        (mouse_column, mouse_row),
+                        new_mouse_cell,
+                        size,
+                        &game,
+                        &player_colors,
+                        grid_size,
+                        border_width,
+                        border_height,
+                    );
+                    buffer.frame_mut().clone_from_slice(&background_buffer);
+                    (mouse_column, mouse_row) = new_mouse_cell;
+                }
+            }
+            Event::MainEventsCleared => {
+                if !game.is_over()
+                    && falling.is_none()
+                    && players[game.active_player() as usize].is_ready()
+                {
+                    match play_turn(&mut game, &mut players) {
+                        Ok(outcome) if last_move_fell(&game) => {
+                            falling = FallingPiece::new(&game, outcome, &player_colors)
+                        }
+                        Ok(outcome) => {
+                            background_buffer = create_background_buffer(
+                                size,
+                                &game,
+                                &player_colors,
+                                grid_size,
+                                border_width,
+                                border_height,
+                            );
+                            update_highlight(
                                 &mut background_buffer,
-                                mouse_column,
-                                mouse_column,
+                                (mouse_column, mouse_row),
+                                (mouse_column, mouse_row),
                                 size,
                                 &game,
                                 &player_colors,
@@ -267,31 +1066,78 @@ pub fn gui_game(
                                 border_width,
                                 border_height,
                             );
+                            draw_game_over_overlay(
+                                &mut background_buffer,
+                                size,
+                                &game,
+                                &player_colors,
+                            );
                             buffer.frame_mut().clone_from_slice(&background_buffer);
-                            if game_over {
-                                println!(
-                                    "Player {} won!!",
-                                    if game.active_player == 0 {
-                                        game.player_count
-                                    } else {
-                                        game.active_player
-                                    }
-                                );
+                            announce_outcome(outcome);
+                            if let Err(e) = config.rate(&game) {
+                                println!("Could not update the ratings: {}", e);
                             }
                         }
-                    };
+                        Err(e) if e.is::<GameError>() => println!("{}", e),
+                        Err(e) => match e.downcast::<SeatEvent>() {
+                            Ok(event) => {
+                                println!("{}", event);
+                                if let Err(e) = event.apply(&mut game) {
+                                    println!("{}", e);
+                                    control_flow.set_exit();
+                                } else if game.is_over() {
+                                    draw_game_over_overlay(
+                                        &mut background_buffer,
+                                        size,
+                                        &game,
+                                        &player_colors,
+                                    );
+                                    buffer.frame_mut().clone_from_slice(&background_buffer);
+                                    announce_outcome(game.status());
+                                }
+                            }
+                            // The player will not come up with a move, e.g. a script that ran out
+                            Err(e) => {
+                                println!("{}", e);
+                                control_flow.set_exit();
+                            }
+                        },
+                    }
                 }
+                window.request_redraw();
             }
-            Event::WindowEvent {
-                event: WindowEvent::CursorMoved { position, .. },
-                ..
-            } => {
-                if !game_over {
-                    (mouse_x, mouse_y) = ((position.x).ceil() as i32, (position.y).ceil() as i32);
-                    let new_mouse_column = ((mouse_x - border_width as i32) / grid_size as i32)
-                        .clamp(0, board_width as i32 - 1)
-                        as u32;
-                    if mouse_column != new_mouse_column {
+            Event::RedrawRequested(_) => {
+                // let start = Instant::now();
+
+                let frame = buffer.frame_mut();
+
+                let radius = calc_piece_radius(grid_size) as i32;
+
+                //FallingPiece
+                let falling_position = falling
+                    .as_ref()
+                    .map(|piece| piece.position(&game, grid_size, border_width, border_height));
+                if let Some(None) = falling_position {
+                    let piece = falling.take().unwrap();
+                    update_background_buffer_column(
+                        &mut background_buffer,
+                        piece.x,
+                        mouse_column,
+                        size,
+                        &game,
+                        &player_colors,
+                        grid_size,
+                        border_width,
+                        border_height,
+                    );
+                    let (new_mouse_column, _) = calc_mouse_cell(
+                        &game,
+                        (mouse_x, mouse_y),
+                        grid_size,
+                        border_width,
+                        border_height,
+                    );
+                    if mouse_column != new_mouse_column && !game.is_over() {
                         update_background_buffer_column(
                             &mut background_buffer,
                             mouse_column,
@@ -303,68 +1149,43 @@ pub fn gui_game(
                             border_width,
                             border_height,
                         );
-                        buffer.frame_mut().clone_from_slice(&background_buffer);
                         mouse_column = new_mouse_column;
                     }
+                    draw_game_over_overlay(&mut background_buffer, size, &game, &player_colors);
+                    frame.clone_from_slice(&background_buffer);
+                    announce_outcome(piece.outcome);
+                    if let Err(e) = config.rate(&game) {
+                        println!("Could not update the ratings: {}", e);
+                    }
                 }
-            }
-            Event::MainEventsCleared => {
-                window.request_redraw();
-            }
-            Event::RedrawRequested(_) => {
-                // let start = Instant::now();
-
-                let frame = buffer.frame_mut();
-
-                let radius = calc_piece_radius(grid_size) as i32;
-                let radius_square = radius.pow(2);
 
                 //CleanUp
+                if let Some(drawn_at) = falling.as_ref().and_then(|piece| piece.drawn_at) {
+                    restore_piece_area(frame, &background_buffer, drawn_at, radius, size);
+                }
                 //TP
                 let top_x: i32 = old_x.clamp(
                     border_width as i32 + radius,
                     (size.width - border_width) as i32 - radius,
                 );
-                for x_offset in -radius..=radius {
-                    let x: u32 = (top_x + x_offset) as u32;
-                    let height = ((radius_square - x_offset.pow(2)) as f64).sqrt() as i32;
-                    for y_offset in -height..=height {
-                        let y = (((border_height + grid_size / 2) as i32) + y_offset) as u32;
-                        if y >= size.height {
-                            continue;
-                        }
+                let top_y = (border_height + grid_size / 2) as i32;
+                restore_piece_area(frame, &background_buffer, (top_x, top_y), radius, size);
 
-                        let index = ((size.width * y + x) * 4) as usize;
-                        frame[index] = background_buffer[index];
-                        frame[index + 1] = background_buffer[index + 1];
-                        frame[index + 2] = background_buffer[index + 2];
-                        frame[index + 3] = background_buffer[index + 3];
-                    }
+                if let (Some(piece), Some(Some(position))) = (&mut falling, falling_position) {
+                    draw_piece(frame, piece.color, position, radius, size);
+                    piece.drawn_at = Some(position);
                 }
                 //TopPiece
                 let top_x: i32 = mouse_x.clamp(
                     border_width as i32 + radius,
                     (size.width - border_width) as i32 - radius,
                 );
-                let color = player_colors[game.active_player as usize];
-                for x_offset in -radius..=radius {
-                    let x: u32 = (top_x + x_offset) as u32;
-                    let height = ((radius_square - x_offset.pow(2)) as f64).sqrt() as i32;
-                    for y_offset in -height..=height {
-                        let y = (((border_height + grid_size / 2) as i32) + y_offset) as u32;
-                        if y >= size.height {
-                            continue;
-                        }
-
-                        let index = ((size.width * y + x) * 4) as usize;
-                        frame[index] = color[0];
-                        frame[index + 1] = color[1];
-                        frame[index + 2] = color[2];
-                        frame[index + 3] = color[3];
-                    }
+                if !game.is_over() {
+                    let color = player_colors[game.active_player() as usize];
+                    draw_piece(frame, color, (top_x, top_y), radius, size);
                 }
 
-                (old_x, old_y) = (mouse_x, mouse_y);
+                old_x = mouse_x;
 
                 // let elapsed = start.elapsed();
                 // println!("Debug: {:?}", elapsed);
@@ -0,0 +1,80 @@
+//! Board shapes read from text files. Every line is a row, the top one first: `.` is a cell and
+//! `#` a blocked cell. Spaces and the end of shorter lines are blocked too, so shapes can be
+//! drawn as they look:
+//!
+//! ```text
+//! .......
+//! #.....#
+//! ##...##
+//! ```
+
+use std::fs;
+
+use crate::Res;
+
+/// The size of a board and which of its cells are blocked.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Layout {
+    /// Number of columns, the length of the longest line.
+    pub width: usize,
+    /// Number of rows, the number of lines.
+    pub height: usize,
+    /// Counted from the bottom left like the cells of a `Board`.
+    pub blocked: Vec<(usize, usize)>,
+}
+impl Layout {
+    /// Reads a layout with one line per row from the top, `.` for a cell and `#` for a blocked
+    /// one.
+    pub fn parse(text: &str) -> Res<Self> {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let width = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let height = rows.len();
+        if width == 0 {
+            return Err("The layout has no rows".into());
+        }
+
+        let mut blocked = Vec::new();
+        for (i, row) in rows.iter().enumerate() {
+            let y = height - 1 - i;
+            let mut cells = row.chars();
+            for x in 0..width {
+                match cells.next() {
+                    Some('.') => {}
+                    Some('#' | ' ') | None => blocked.push((x, y)),
+                    Some(c) => {
+                        return Err(format!(
+                            "Unknown cell '{}' in row {} of the layout, use '.' or '#'",
+                            c,
+                            i + 1
+                        )
+                        .into())
+                    }
+                }
+            }
+        }
+        if blocked.len() == width * height {
+            return Err("Every cell of the layout is blocked".into());
+        }
+        blocked.sort_by_key(|&(x, y)| (y, x));
+        Ok(Layout {
+            width,
+            height,
+            blocked,
+        })
+    }
+
+    /// Reads the layout file at `path`, see `parse`.
+    pub fn load(path: &str) -> Res<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read the layout {}: {}", path, e))?;
+        Layout::parse(&text)
+    }
+}
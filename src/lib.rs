@@ -0,0 +1,1136 @@
+//! Connect-N engine: a Connect Four style game for any number of players, any board size and
+//! any line length needed to win.
+//!
+//! ```
+//! use connect_n::{take_turn, BoardBackend, Game, TurnOutcome};
+//!
+//! let mut game = Game::new(2, 4, 7, 6);
+//! let outcome = take_turn(&mut game, &mut |_: &Game| Ok(3)).unwrap();
+//! assert_eq!(outcome, TurnOutcome::Continue);
+//! assert_eq!(game.board().occupance(3, 0), Some(1));
+//! ```
+
+use std::{
+    error::Error,
+    fmt::{Debug, Display},
+};
+
+pub mod ai;
+pub mod bitboard;
+pub mod engine;
+mod error;
+pub mod layout;
+pub mod net;
+mod notation;
+mod player;
+pub mod rating;
+
+pub use error::GameError;
+pub use player::{play_turn, Player, ScriptedPlayer};
+
+/// Result of everything that can fail for more than a rule, like files or connections.
+pub type Res<T> = Result<T, Box<dyn Error>>;
+
+/// Largest width, height or depth of a board.
+pub const MAX_BOARD_SIZE: usize = 1000;
+/// Largest number of cells of a board, the pillars of a 3D board included.
+pub const MAX_CELLS: usize = 1 << 20;
+/// Largest number of players of a game.
+pub const MAX_PLAYERS: u32 = 100;
+
+/// What a move did to the game, and so the status of the game after it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TurnOutcome {
+    /// Nobody won yet and there is room left.
+    Continue,
+    /// The player with this index (starting at 0) completed a line.
+    Win(u32),
+    /// The board is full and nobody won.
+    Draw,
+}
+
+/// Asks `input_function` for a column, places the active player's piece there and passes the
+/// turn on.
+pub fn take_turn(
+    game: &mut Game,
+    input_function: &mut dyn FnMut(&Game) -> Res<usize>,
+) -> Res<TurnOutcome> {
+    take_action(game, &mut |game: &Game| {
+        input_function(game).map(Action::Place)
+    })
+}
+
+/// Like `take_turn`, for players that may also pop pieces out.
+pub fn take_action(
+    game: &mut Game,
+    input_function: &mut dyn FnMut(&Game) -> Res<Action>,
+) -> Res<TurnOutcome> {
+    let action = input_function(game)?;
+    match game.play(action) {
+        Ok(_) => {
+            game.end_turn();
+            Ok(game.status())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Checks that a game with these rules can be played.
+pub fn validate_rules(
+    player_count: u32,
+    connect_size: usize,
+    board_width: usize,
+    board_height: usize,
+) -> Result<(), GameError> {
+    validate_rules_3d(player_count, connect_size, board_width, board_height, 1)
+}
+
+/// Like `validate_rules` for boards that are `board_depth` pillars deep, see `Game::with_depth`.
+pub fn validate_rules_3d(
+    player_count: u32,
+    connect_size: usize,
+    board_width: usize,
+    board_height: usize,
+    board_depth: usize,
+) -> Result<(), GameError> {
+    let invalid = |reason: &str| Err(GameError::InvalidConfig(reason.to_string()));
+    if player_count == 0 {
+        return invalid("There has to be at least one player");
+    }
+    if player_count > MAX_PLAYERS {
+        return invalid(&format!("There can be at most {} players", MAX_PLAYERS));
+    }
+    if board_width == 0 || board_height == 0 {
+        return invalid("The board needs a width and height of at least 1");
+    }
+    if board_depth == 0 {
+        return invalid("The board needs a depth of at least 1");
+    }
+    if board_width.max(board_height).max(board_depth) > MAX_BOARD_SIZE {
+        return invalid(&format!(
+            "The board can be at most {} cells wide, high and deep",
+            MAX_BOARD_SIZE
+        ));
+    }
+    let cells = board_width
+        .checked_mul(board_height)
+        .and_then(|cells| cells.checked_mul(board_depth));
+    if cells.is_none_or(|cells| cells > MAX_CELLS) {
+        return invalid(&format!("The board can have at most {} cells", MAX_CELLS));
+    }
+    if connect_size == 0 {
+        return invalid("The connect size has to be at least 1");
+    }
+    if connect_size > board_width.max(board_height).max(board_depth) {
+        let size = match board_depth {
+            1 => format!("{}x{}", board_width, board_height),
+            _ => format!("{}x{}x{}", board_width, board_height, board_depth),
+        };
+        return invalid(&format!(
+            "A line of {} does not fit on a {} board",
+            connect_size, size
+        ));
+    }
+    Ok(())
+}
+
+/// What a player does on their turn.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    /// Drops a piece into the column.
+    Place(usize),
+    /// Pulls the player's own piece out of the bottom of the column, the pieces above it fall
+    /// down. Only allowed in PopOut games.
+    Pop(usize),
+    /// Puts a piece on the empty cell (x, y). Only allowed in games without gravity.
+    PlaceAt(usize, usize),
+}
+
+/// Whether a `Move` put a piece on the board or popped one out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MoveKind {
+    /// A piece was dropped or put on a cell.
+    Place,
+    /// A piece was popped out of the bottom row, see `Action::Pop`.
+    Pop,
+}
+
+/// A piece that has been placed or popped out, `player` starts at 0. Pops are always at `y` 0.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Move {
+    /// Column of the move.
+    pub x: usize,
+    /// Row the piece landed in.
+    pub y: usize,
+    /// Player who made the move.
+    pub player: u32,
+    /// Whether the piece was placed or popped.
+    pub kind: MoveKind,
+}
+
+/// One field of a `Board`.
+#[derive(Clone)]
+pub struct Cell {
+    occupance: u32,
+    blocked: bool,
+}
+impl Cell {
+    fn new() -> Self {
+        Cell {
+            occupance: 0,
+            blocked: false,
+        }
+    }
+
+    /// 0 for an empty or blocked cell, otherwise the index of the player + 1.
+    pub fn occupance(&self) -> u32 {
+        self.occupance
+    }
+
+    /// Whether a piece can still go on the cell.
+    pub fn is_empty(&self) -> bool {
+        self.occupance == 0 && !self.blocked
+    }
+
+    /// Whether the cell is part of the board's shape rather than a place for pieces.
+    pub fn is_blocked(&self) -> bool {
+        self.blocked
+    }
+
+    fn occupy(&mut self, player: u32) {
+        self.occupance = player + 1
+    }
+
+    fn clear(&mut self) {
+        self.occupance = 0
+    }
+}
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        self.occupance == other.occupance && self.blocked == other.blocked
+    }
+}
+impl Debug for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.blocked {
+            write!(f, "# ")
+        } else {
+            write!(f, "{} ", self.occupance)
+        }
+    }
+}
+
+/// The eight neighbours of a cell, y grows upwards.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    /// One column to the left.
+    Left,
+    /// One column to the left and one row up.
+    TopLeft,
+    /// One row up.
+    Top,
+    /// One column to the right and one row up.
+    TopRight,
+    /// One column to the right.
+    Right,
+    /// One column to the right and one row down.
+    DownRight,
+    /// One row down.
+    Down,
+    /// One column to the left and one row down.
+    DownLeft,
+}
+impl Direction {
+    /// Pairs of opposite directions, one for each axis a line can lie on.
+    pub const AXES: [(Direction, Direction); 4] = [
+        (Direction::Down, Direction::Top),
+        (Direction::Left, Direction::Right),
+        (Direction::DownLeft, Direction::TopRight),
+        (Direction::DownRight, Direction::TopLeft),
+    ];
+
+    /// Steps in x, y and z of the 13 axes a line can lie on in a 3D board, one for each pair of
+    /// opposite directions. y grows upwards like in `to_tuple`.
+    pub const AXES_3D: [(i64, i64, i64); 13] = [
+        (1, 0, 0),
+        (0, 1, 0),
+        (0, 0, 1),
+        (1, 1, 0),
+        (1, -1, 0),
+        (1, 0, 1),
+        (1, 0, -1),
+        (0, 1, 1),
+        (0, 1, -1),
+        (1, 1, 1),
+        (1, 1, -1),
+        (1, -1, 1),
+        (1, -1, -1),
+    ];
+
+    /// Step in x and y, y grows upwards.
+    pub fn to_tuple(self) -> (i64, i64) {
+        match self {
+            Self::Left => (-1, 0),
+            Self::TopLeft => (-1, 1),
+            Self::Top => (0, 1),
+            Self::TopRight => (1, 1),
+            Self::Right => (1, 0),
+            Self::DownRight => (1, -1),
+            Self::Down => (0, -1),
+            Self::DownLeft => (-1, -1),
+        }
+    }
+}
+
+/// How the edges of the board connect. Lines that run into a wrapping edge go on at the opposite
+/// one.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum Topology {
+    /// Lines end at the edges.
+    #[default]
+    Flat,
+    /// The left and right edges wrap around.
+    Cylinder,
+    /// Both the left and right and the top and bottom edges wrap around.
+    Torus,
+}
+impl Topology {
+    /// Reads the names written by `Display`: flat, cylinder or torus.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "flat" => Some(Topology::Flat),
+            "cylinder" => Some(Topology::Cylinder),
+            "torus" => Some(Topology::Torus),
+            _ => None,
+        }
+    }
+
+    /// Whether lines wrap from the left to the right edge.
+    pub fn wraps_x(self) -> bool {
+        self != Topology::Flat
+    }
+
+    /// Whether lines wrap from the top to the bottom edge.
+    pub fn wraps_y(self) -> bool {
+        self == Topology::Torus
+    }
+
+    /// Maps (x, y) onto the board, `None` past an edge that does not wrap.
+    pub fn wrap(self, x: i64, y: i64, width: usize, height: usize) -> Option<(usize, usize)> {
+        let x = if self.wraps_x() {
+            x.rem_euclid(width as i64)
+        } else {
+            x
+        };
+        let y = if self.wraps_y() {
+            y.rem_euclid(height as i64)
+        } else {
+            y
+        };
+        if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+            None
+        } else {
+            Some((x as usize, y as usize))
+        }
+    }
+
+    /// Number of cells after which a line with the step (x_dir, y_dir) is back where it started,
+    /// `None` if it runs into an edge first.
+    pub fn period(self, (x_dir, y_dir): (i64, i64), width: usize, height: usize) -> Option<usize> {
+        match (x_dir != 0 && self.wraps_x(), y_dir != 0 && self.wraps_y()) {
+            (true, true) => Some(width / gcd(width, height) * height),
+            (true, false) if y_dir == 0 => Some(width),
+            (false, true) if x_dir == 0 => Some(height),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Topology {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Topology::Flat => write!(f, "flat"),
+            Topology::Cylinder => write!(f, "cylinder"),
+            Topology::Torus => write!(f, "torus"),
+        }
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Storage of the pieces on a board. `Board` keeps one `Cell` per field, `BitBoard` keeps one
+/// bitmask per player for fast searches. Both can be swapped in `Game` and checked against each
+/// other.
+///
+/// [`BitBoard`]: bitboard::BitBoard
+pub trait BoardBackend: Clone {
+    /// An empty board of `width` columns and `height` rows.
+    fn new(width: usize, height: usize) -> Self;
+
+    /// Number of columns.
+    fn width(&self) -> usize;
+
+    /// Number of rows.
+    fn height(&self) -> usize;
+
+    /// `None` outside of the board and on blocked cells, otherwise 0 for an empty cell and
+    /// player + 1 for a piece. So lines end at blocked cells just like at the edges.
+    fn occupance(&self, x: usize, y: usize) -> Option<u32>;
+
+    /// Puts a piece of `player` on (x, y), unless the cell is blocked.
+    fn occupy(&mut self, x: usize, y: usize, player: u32);
+
+    /// Takes the piece off (x, y), blocked cells stay blocked.
+    fn clear(&mut self, x: usize, y: usize);
+
+    /// Blocks the empty cell (x, y) for good.
+    fn block(&mut self, x: usize, y: usize);
+
+    /// Whether (x, y) is part of the board's shape rather than a place for pieces.
+    fn is_blocked(&self, x: usize, y: usize) -> bool;
+
+    /// Whether every cell that is not blocked holds a piece.
+    fn is_full(&self) -> bool;
+
+    /// The blocked cells, row by row from the bottom left.
+    fn blocked_cells(&self) -> Vec<(usize, usize)> {
+        (0..self.height())
+            .flat_map(|y| (0..self.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.is_blocked(x, y))
+            .collect()
+    }
+
+    /// Every cell as `(x, y, occupance)`, row by row from the bottom left. Blocked cells are 0.
+    fn cells(&self) -> impl Iterator<Item = (usize, usize, u32)> + '_ {
+        (0..self.height()).flat_map(move |y| {
+            (0..self.width()).map(move |x| (x, y, self.occupance(x, y).unwrap_or(0)))
+        })
+    }
+
+    /// Number of cells next to (x, y) in `direction` that hold the same as (x, y). A line that
+    /// wraps all the way around stops before (x, y).
+    fn get_line_length(
+        &self,
+        x: usize,
+        y: usize,
+        direction: Direction,
+        topology: Topology,
+    ) -> usize {
+        let (x_dir, y_dir) = direction.to_tuple();
+        let occupance = self.occupance(x, y);
+        let (mut line_x, mut line_y) = (x, y);
+        let mut length = 0;
+        loop {
+            match topology.wrap(
+                line_x as i64 + x_dir,
+                line_y as i64 + y_dir,
+                self.width(),
+                self.height(),
+            ) {
+                Some(next) if next != (x, y) && self.occupance(next.0, next.1) == occupance => {
+                    (line_x, line_y) = next;
+                    length += 1;
+                }
+                _ => return length,
+            }
+        }
+    }
+
+    /// The cells counted by `get_line_length`, nearest first.
+    fn get_line_cells(
+        &self,
+        x: usize,
+        y: usize,
+        direction: Direction,
+        topology: Topology,
+    ) -> Vec<(usize, usize)> {
+        let (x_dir, y_dir) = direction.to_tuple();
+        let mut cell = (x, y);
+        (0..self.get_line_length(x, y, direction, topology))
+            .map(|_| {
+                cell = topology
+                    .wrap(
+                        cell.0 as i64 + x_dir,
+                        cell.1 as i64 + y_dir,
+                        self.width(),
+                        self.height(),
+                    )
+                    .expect("the line was just measured");
+                cell
+            })
+            .collect()
+    }
+
+    /// Length of the line through (x, y) along an axis of opposite directions. When the line
+    /// goes all the way around both directions meet, every cell still counts only once.
+    fn get_axis_length(
+        &self,
+        x: usize,
+        y: usize,
+        (backward, forward): (Direction, Direction),
+        topology: Topology,
+    ) -> usize {
+        let length = 1
+            + self.get_line_length(x, y, backward, topology)
+            + self.get_line_length(x, y, forward, topology);
+        match topology.period(forward.to_tuple(), self.width(), self.height()) {
+            Some(period) => length.min(period),
+            None => length,
+        }
+    }
+
+    /// Whether the piece at (x, y) is part of a line of at least `connect_size` pieces.
+    fn has_line_through(
+        &self,
+        x: usize,
+        y: usize,
+        connect_size: usize,
+        topology: Topology,
+    ) -> bool {
+        Direction::AXES
+            .iter()
+            .any(|&axis| self.get_axis_length(x, y, axis, topology) >= connect_size)
+    }
+}
+
+/// The default board, one `Cell` per field stored row by row from the bottom.
+#[derive(Clone)]
+pub struct Board {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+impl Board {
+    /// The cell at (x, y), `None` outside of the board.
+    pub fn get_cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        if x >= self.width || y >= self.height {
+            None
+        } else {
+            Some(&self.cells[y * self.width + x])
+        }
+    }
+
+    fn get_mut_cell(&mut self, x: usize, y: usize) -> Option<&mut Cell> {
+        if x >= self.width || y >= self.height {
+            None
+        } else {
+            Some(&mut self.cells[y * self.width + x])
+        }
+    }
+}
+impl BoardBackend for Board {
+    fn new(width: usize, height: usize) -> Self {
+        Board {
+            width,
+            height,
+            cells: vec![Cell::new(); width * height],
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn occupance(&self, x: usize, y: usize) -> Option<u32> {
+        self.get_cell(x, y)
+            .filter(|cell| !cell.blocked)
+            .map(|cell| cell.occupance)
+    }
+
+    fn occupy(&mut self, x: usize, y: usize, player: u32) {
+        if let Some(cell) = self.get_mut_cell(x, y).filter(|cell| !cell.blocked) {
+            cell.occupy(player);
+        }
+    }
+
+    fn clear(&mut self, x: usize, y: usize) {
+        if let Some(cell) = self.get_mut_cell(x, y) {
+            cell.clear();
+        }
+    }
+
+    fn block(&mut self, x: usize, y: usize) {
+        if let Some(cell) = self.get_mut_cell(x, y) {
+            cell.clear();
+            cell.blocked = true;
+        }
+    }
+
+    fn is_blocked(&self, x: usize, y: usize) -> bool {
+        self.get_cell(x, y).is_some_and(|cell| cell.blocked)
+    }
+
+    fn is_full(&self) -> bool {
+        self.cells.iter().all(|cell| !cell.is_empty())
+    }
+}
+impl Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines = self
+            .cells
+            .chunks(self.width)
+            .rev()
+            .fold(String::new(), |string, vec| {
+                format!("{}\n{:?}", string, vec)
+            });
+        write!(f, "{}", lines)
+    }
+}
+
+/// A running game: the board, whose turn it is and every move so far.
+#[derive(Clone)]
+pub struct Game<B: BoardBackend = Board> {
+    board: B,
+    player_count: u32,
+    connect_size: usize,
+    active_player: u32,
+    history: Vec<Move>,
+    undone: Vec<Move>,
+    status: TurnOutcome,
+    /// Players that left the game, their turns are skipped.
+    forfeited: Vec<bool>,
+    pop_out: bool,
+    gravity: bool,
+    topology: Topology,
+    /// Rows of pillars of a 3D board, 1 for a flat one. See `with_depth`.
+    depth: usize,
+}
+impl Game {
+    /// Creates an empty game on the default `Board`. Player 0 starts.
+    pub fn new(
+        player_count: u32,
+        connect_size: usize,
+        board_width: usize,
+        board_height: usize,
+    ) -> Self {
+        Game::with_backend(player_count, connect_size, board_width, board_height)
+    }
+}
+impl<B: BoardBackend> Game<B> {
+    /// Creates an empty game on any board backend.
+    pub fn with_backend(
+        player_count: u32,
+        connect_size: usize,
+        board_width: usize,
+        board_height: usize,
+    ) -> Self {
+        Game {
+            board: B::new(board_width, board_height),
+            player_count,
+            connect_size,
+            active_player: 0,
+            history: Vec::new(),
+            undone: Vec::new(),
+            status: TurnOutcome::Continue,
+            forfeited: vec![false; player_count as usize],
+            pop_out: false,
+            gravity: true,
+            topology: Topology::Flat,
+            depth: 1,
+            // players: vec![Player::new(); player_count.try_into().unwrap()],
+        }
+    }
+
+    /// Turns the PopOut variant on or off, in which players may pop their own pieces out of the
+    /// bottom row instead of placing one.
+    pub fn with_pop_out(mut self, pop_out: bool) -> Self {
+        self.pop_out = pop_out;
+        self
+    }
+
+    /// Turns gravity on or off. Without it pieces go on any empty cell like in tic-tac-toe or
+    /// gomoku, see `place_piece_at`.
+    pub fn with_gravity(mut self, gravity: bool) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Blocks `cells` of the empty board, which gives it another shape than a rectangle. See
+    /// [`Layout`](layout::Layout) for reading them from a file.
+    pub fn with_blocked_cells(mut self, cells: &[(usize, usize)]) -> Self {
+        for &(x, y) in cells {
+            self.board.block(x, y);
+        }
+        self
+    }
+
+    /// Lets lines wrap around the edges of the board.
+    pub fn with_topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Makes the board three dimensional, `depth` rows of pillars deep like the 4x4x4 of Score
+    /// Four. Pieces fall down the pillars and lines may run along all 13 axes of the cube. The
+    /// pillars are the columns of the board, see `pillar`, so everything that takes a column
+    /// takes a pillar. It starts over with an empty board, so call it before
+    /// `with_blocked_cells`. 3D boards do not wrap around.
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        let depth = depth.max(1);
+        self.board = B::new(self.width() * depth, self.board.height());
+        self.depth = depth;
+        self
+    }
+
+    /// Copies the position, history included, onto another board backend.
+    pub fn to_backend<C: BoardBackend>(&self) -> Game<C> {
+        let mut board = C::new(self.board.width(), self.board.height());
+        for (x, y) in self.board.blocked_cells() {
+            board.block(x, y);
+        }
+        for (x, y, occupance) in self.board.cells() {
+            if occupance > 0 {
+                board.occupy(x, y, occupance - 1);
+            }
+        }
+        Game {
+            board,
+            player_count: self.player_count,
+            connect_size: self.connect_size,
+            active_player: self.active_player,
+            history: self.history.clone(),
+            undone: self.undone.clone(),
+            status: self.status,
+            forfeited: self.forfeited.clone(),
+            pop_out: self.pop_out,
+            gravity: self.gravity,
+            topology: self.topology,
+            depth: self.depth,
+        }
+    }
+
+    /// The board with the pieces, read it through `BoardBackend`.
+    pub fn board(&self) -> &B {
+        &self.board
+    }
+
+    /// Number of seats, forfeited players included.
+    pub fn player_count(&self) -> u32 {
+        self.player_count
+    }
+
+    /// Pieces in a row needed to win.
+    pub fn connect_size(&self) -> usize {
+        self.connect_size
+    }
+
+    /// Whether pieces may be popped out, see `with_pop_out`.
+    pub fn pop_out(&self) -> bool {
+        self.pop_out
+    }
+
+    /// Whether pieces fall down their column, true unless turned off with `with_gravity`.
+    pub fn gravity(&self) -> bool {
+        self.gravity
+    }
+
+    /// How lines wrap around the edges, see `with_topology`.
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Width of the board. For a 3D board this is the width of a layer, the board has
+    /// `width * depth` columns.
+    pub fn width(&self) -> usize {
+        self.board.width() / self.depth
+    }
+
+    /// Rows of pillars, 1 unless the board is three dimensional.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Column of the pillar at (x, z) of a 3D board, z is the row of pillars counted from the
+    /// front.
+    pub fn pillar(&self, x: usize, z: usize) -> usize {
+        z * self.width() + x
+    }
+
+    /// The (x, z) position of the pillar in `column`, the opposite of `pillar`.
+    pub fn pillar_position(&self, column: usize) -> (usize, usize) {
+        (column % self.width(), column / self.width())
+    }
+
+    /// Index of the player whose turn it is, starting at 0.
+    pub fn active_player(&self) -> u32 {
+        self.active_player
+    }
+
+    /// Every move that has been played, oldest first.
+    pub fn history(&self) -> &[Move] {
+        &self.history
+    }
+
+    /// `Continue` while the game is running, otherwise who won or that it is a draw.
+    pub fn status(&self) -> TurnOutcome {
+        self.status
+    }
+
+    /// Whether somebody won or it is a draw.
+    pub fn is_over(&self) -> bool {
+        self.status != TurnOutcome::Continue
+    }
+
+    /// Index of the player who won, starting at 0.
+    pub fn winner(&self) -> Option<u32> {
+        match self.status {
+            TurnOutcome::Win(player) => Some(player),
+            _ => None,
+        }
+    }
+
+    /// Passes the turn to the next player that has not forfeited. Once the game is over the turn
+    /// stays where it is.
+    pub fn end_turn(&mut self) {
+        if !self.is_over() {
+            self.active_player = self.next_player(self.active_player);
+        }
+    }
+
+    /// The player after `player` that has not forfeited.
+    fn next_player(&self, player: u32) -> u32 {
+        (1..=self.player_count)
+            .map(|i| (player + i) % self.player_count)
+            .find(|&next| !self.has_forfeited(next))
+            .unwrap_or(player)
+    }
+
+    /// Takes `player` out of the game, the others play on without them. The last player left wins.
+    pub fn forfeit(&mut self, player: u32) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameAlreadyOver);
+        }
+        if player >= self.player_count {
+            return Err(GameError::UnknownPlayer(player));
+        }
+        self.forfeited[player as usize] = true;
+        let mut remaining = (0..self.player_count).filter(|&p| !self.has_forfeited(p));
+        match (remaining.next(), remaining.next()) {
+            (Some(winner), None) => self.status = TurnOutcome::Win(winner),
+            _ if self.active_player == player => self.end_turn(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Whether `player` left the game, see `forfeit`.
+    pub fn has_forfeited(&self, player: u32) -> bool {
+        self.forfeited.get(player as usize) == Some(&true)
+    }
+
+    /// Drops a piece of the active player into column `x` and returns the row it landed in.
+    /// The turn is not passed on, see `end_turn`.
+    pub fn place_piece(&mut self, x: usize) -> Result<usize, GameError> {
+        if self.is_over() {
+            return Err(GameError::GameAlreadyOver);
+        }
+        if !self.gravity {
+            return Err(GameError::GravityDisabled);
+        }
+        if x >= self.board.width() {
+            return Err(GameError::ColumnOutOfRange {
+                column: x,
+                width: self.board.width(),
+            });
+        }
+        let Some(y) = self.landing_row(x) else {
+            return Err(GameError::ColumnFull(x));
+        };
+        self.board.occupy(x, y, self.active_player);
+        self.history.push(Move {
+            x,
+            y,
+            player: self.active_player,
+            kind: MoveKind::Place,
+        });
+        self.undone.clear();
+        self.status = self.outcome_of_last_move();
+        Ok(y)
+    }
+
+    /// Row a piece dropped into column `x` lands in: the lowest empty cell, which rests on the
+    /// bottom, a piece or a blocked cell. `None` once the column has no empty cell left.
+    pub fn landing_row(&self, x: usize) -> Option<usize> {
+        (0..self.board.height()).find(|&y| self.board.occupance(x, y) == Some(0))
+    }
+
+    /// Puts a piece of the active player on the empty cell (x, y) of a game without gravity. The
+    /// turn is not passed on, see `end_turn`.
+    pub fn place_piece_at(&mut self, x: usize, y: usize) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameAlreadyOver);
+        }
+        if self.gravity {
+            return Err(GameError::GravityEnabled);
+        }
+        if x >= self.board.width() {
+            return Err(GameError::ColumnOutOfRange {
+                column: x,
+                width: self.board.width(),
+            });
+        }
+        if y >= self.board.height() {
+            return Err(GameError::RowOutOfRange {
+                row: y,
+                height: self.board.height(),
+            });
+        }
+        if self.board.is_blocked(x, y) {
+            return Err(GameError::CellBlocked { x, y });
+        }
+        if self.board.occupance(x, y) != Some(0) {
+            return Err(GameError::CellOccupied { x, y });
+        }
+        self.board.occupy(x, y, self.active_player);
+        self.history.push(Move {
+            x,
+            y,
+            player: self.active_player,
+            kind: MoveKind::Place,
+        });
+        self.undone.clear();
+        self.status = self.outcome_of_last_move();
+        Ok(())
+    }
+
+    /// Pops the active player's piece out of the bottom of column `x`. The turn is not passed on,
+    /// see `end_turn`.
+    pub fn pop_piece(&mut self, x: usize) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameAlreadyOver);
+        }
+        // Without gravity nothing would fall into the gap
+        if !self.pop_out || !self.gravity {
+            return Err(GameError::PopOutDisabled);
+        }
+        if x >= self.board.width() {
+            return Err(GameError::ColumnOutOfRange {
+                column: x,
+                width: self.board.width(),
+            });
+        }
+        if self.board.occupance(x, 0) != Some(self.active_player + 1) {
+            return Err(GameError::CanNotPop(x));
+        }
+        self.shift_column_down(x);
+        self.history.push(Move {
+            x,
+            y: 0,
+            player: self.active_player,
+            kind: MoveKind::Pop,
+        });
+        self.undone.clear();
+        self.status = self.outcome_of_last_move();
+        Ok(())
+    }
+
+    /// Plays `action` for the active player and returns the resulting move.
+    pub fn play(&mut self, action: Action) -> Result<Move, GameError> {
+        match action {
+            Action::Place(x) => self.place_piece(x)?,
+            Action::Pop(x) => {
+                self.pop_piece(x)?;
+                0
+            }
+            Action::PlaceAt(x, y) => {
+                self.place_piece_at(x, y)?;
+                y
+            }
+        };
+        Ok(*self.history.last().expect("a move was just played"))
+    }
+
+    /// Whether `player` has a piece in the bottom row to pop out.
+    pub fn can_pop(&self, player: u32) -> bool {
+        self.pop_out
+            && (0..self.board.width()).any(|x| self.board.occupance(x, 0) == Some(player + 1))
+    }
+
+    /// Removes the bottom piece of column `x`, everything above it falls down one row.
+    fn shift_column_down(&mut self, x: usize) {
+        for y in 0..self.stack_height(x) {
+            match self.board.occupance(x, y + 1) {
+                Some(occupance) if occupance > 0 => self.board.occupy(x, y, occupance - 1),
+                _ => self.board.clear(x, y),
+            }
+        }
+    }
+
+    /// Lifts column `x` up by one row and puts a piece of `player` at the bottom, undoing a pop.
+    fn shift_column_up(&mut self, x: usize, player: u32) {
+        for y in (1..self.stack_height(x)).rev() {
+            match self.board.occupance(x, y - 1) {
+                Some(occupance) if occupance > 0 => self.board.occupy(x, y, occupance - 1),
+                _ => self.board.clear(x, y),
+            }
+        }
+        self.board.occupy(x, 0, player);
+    }
+
+    /// Rows of column `x` below its lowest blocked cell. Pops only move these, the pieces on the
+    /// blocked cell rest on it.
+    fn stack_height(&self, x: usize) -> usize {
+        (0..self.board.height())
+            .find(|&y| self.board.is_blocked(x, y))
+            .unwrap_or(self.board.height())
+    }
+
+    /// Returns the cells of the line through (x, y) that is long enough to win, if there is one.
+    pub fn chech_win(&self, x: usize, y: usize) -> Option<Vec<(usize, usize)>> {
+        if self.depth > 1 {
+            return Direction::AXES_3D
+                .into_iter()
+                .map(|step| self.line_3d(x, y, step))
+                .find(|cells| cells.len() >= self.connect_size);
+        }
+        Direction::AXES
+            .into_iter()
+            .map(|axis| (axis, self.board.get_axis_length(x, y, axis, self.topology)))
+            .find(|&(_, length)| length >= self.connect_size)
+            .map(|((backward, forward), length)| {
+                let mut cells = self.board.get_line_cells(x, y, backward, self.topology);
+                cells.reverse();
+                cells.push((x, y));
+                cells.extend(self.board.get_line_cells(x, y, forward, self.topology));
+                // A line around the whole board reached every cell from both sides
+                cells.truncate(length);
+                cells
+            })
+    }
+
+    /// Whether the piece at (x, y) is part of a line long enough to win.
+    fn has_line_through(&self, x: usize, y: usize) -> bool {
+        if self.depth > 1 {
+            return Direction::AXES_3D
+                .into_iter()
+                .any(|step| self.line_3d(x, y, step).len() >= self.connect_size);
+        }
+        self.board
+            .has_line_through(x, y, self.connect_size, self.topology)
+    }
+
+    /// Cells of the line through the piece in column `x` at height `y` of a 3D board, going
+    /// along `step` in x, y and z.
+    fn line_3d(&self, x: usize, y: usize, step: (i64, i64, i64)) -> Vec<(usize, usize)> {
+        let player = match self.board.occupance(x, y) {
+            Some(occupance) if occupance > 0 => occupance,
+            _ => return Vec::new(),
+        };
+        let (pillar_x, pillar_z) = self.pillar_position(x);
+        let (width, depth) = (self.width() as i64, self.depth as i64);
+        let cell = |n: i64| {
+            let x = pillar_x as i64 + n * step.0;
+            let y = y as i64 + n * step.1;
+            let z = pillar_z as i64 + n * step.2;
+            if x < 0 || x >= width || y < 0 || z < 0 || z >= depth {
+                return None;
+            }
+            let column = self.pillar(x as usize, z as usize);
+            (self.board.occupance(column, y as usize) == Some(player))
+                .then_some((column, y as usize))
+        };
+        let mut cells: Vec<(usize, usize)> = (1..).map_while(|n| cell(-n)).collect();
+        cells.reverse();
+        cells.push((x, y));
+        cells.extend((1..).map_while(cell));
+        cells
+    }
+
+    /// The winning line completed by the last move, if it won the game.
+    pub fn winning_line(&self) -> Option<Vec<(usize, usize)>> {
+        let last_move = self.history.last()?;
+        match last_move.kind {
+            MoveKind::Place => self.chech_win(last_move.x, last_move.y),
+            MoveKind::Pop => {
+                let winner = self.winner()?;
+                (0..self.board.height())
+                    .filter(|&y| self.board.occupance(last_move.x, y) == Some(winner + 1))
+                    .find_map(|y| self.chech_win(last_move.x, y))
+            }
+        }
+    }
+
+    /// Outcome of the last move, `Continue` for a game without moves.
+    fn outcome_of_last_move(&self) -> TurnOutcome {
+        let Some(last_move) = self.history.last() else {
+            return TurnOutcome::Continue;
+        };
+        let winner = match last_move.kind {
+            MoveKind::Place => self
+                .has_line_through(last_move.x, last_move.y)
+                .then_some(last_move.player),
+            // Every piece of the column moved, so a pop can complete lines of several players.
+            // The one who popped wins, otherwise the first of the others in turn order.
+            MoveKind::Pop => (0..self.player_count)
+                .map(|i| (last_move.player + i) % self.player_count)
+                .find(|&player| {
+                    (0..self.board.height()).any(|y| {
+                        self.board.occupance(last_move.x, y) == Some(player + 1)
+                            && self.has_line_through(last_move.x, y)
+                    })
+                }),
+        };
+        match winner {
+            Some(player) => TurnOutcome::Win(player),
+            // In PopOut a full board goes on as long as the next player can pop
+            None if self.no_room_left() && !self.can_pop(self.next_player(last_move.player)) => {
+                TurnOutcome::Draw
+            }
+            None => TurnOutcome::Continue,
+        }
+    }
+
+    /// Whether no piece can be placed anymore. With gravity every empty cell is a `landing_row`
+    /// once the cells below it are taken, so that is once the board is full.
+    fn no_room_left(&self) -> bool {
+        self.board.is_full()
+    }
+
+    /// Whether the board is full without anybody having won.
+    pub fn is_draw(&self) -> bool {
+        self.status == TurnOutcome::Draw
+    }
+
+    /// Takes back the last move and gives the turn back to the player who made it.
+    pub fn undo(&mut self) -> Option<Move> {
+        let last_move = self.history.pop()?;
+        match last_move.kind {
+            MoveKind::Place => self.board.clear(last_move.x, last_move.y),
+            MoveKind::Pop => self.shift_column_up(last_move.x, last_move.player),
+        }
+        self.active_player = last_move.player;
+        // Moves are only played while the game is running
+        self.status = TurnOutcome::Continue;
+        self.undone.push(last_move);
+        Some(last_move)
+    }
+
+    /// Plays the last undone move again. Placing a new piece forgets all undone moves.
+    pub fn redo(&mut self) -> Option<Move> {
+        if self.is_over() {
+            return None;
+        }
+        let next_move = self.undone.pop()?;
+        match next_move.kind {
+            MoveKind::Place => self
+                .board
+                .occupy(next_move.x, next_move.y, next_move.player),
+            MoveKind::Pop => self.shift_column_down(next_move.x),
+        }
+        self.active_player = next_move.player;
+        self.history.push(next_move);
+        self.status = self.outcome_of_last_move();
+        self.end_turn();
+        Some(next_move)
+    }
+}
@@ -0,0 +1,879 @@
+//! Playing over TCP. One instance hosts the game, the others join it and claim a seat each.
+//!
+//! The protocol is line based text, columns and seats start at 0:
+//!
+//! | client → host   | host → client                                          |
+//! |-----------------|--------------------------------------------------------|
+//! | `join [seat]`   | `welcome <seat> <width> <height> <connect> <players>`  |
+//! |                 | `history <step>...` (what happened before joining)     |
+//! |                 | `start` (every seat is taken)                          |
+//! | `play <column>` | `moved <seat> <column>` (sent to everybody)            |
+//! |                 | `error <message>` (the move or join was rejected)      |
+//!
+//! The host owns the real game and checks every move with `take_turn`, the clients replay the
+//! `moved` lines on a copy.
+//!
+//! A dedicated [`server`] hosts many games as lobbies. Instead of `join` its clients send one of:
+//!
+//! - `list`, answered by `lobby <id> <width> <height> <connect> <players> <free seats>` for every
+//!   lobby and a closing `listed`
+//! - `create <width> <height> <connect> <players>`, answered by `created <id>`
+//! - `enter <id> [seat]`, answered like `join`
+//! - `spectate <id>`, answered by `watching <width> <height> <connect> <players>`, `history`,
+//!   `start` and then every `moved`
+//! - `rejoin <id> <token>`, answered like `enter`. Seated players get a `session <token>` after
+//!   their `welcome`, with it they can take their seat back after the connection broke.
+//!
+//! While a seat is empty the server sends `waiting <seat> <seconds>` once it is that seat's turn.
+//! If nobody rejoins in time it sends `skipped <seat>` or `forfeited <seat>`, depending on the
+//! server's `TimeoutPolicy`. A step of the `history` is a column, `s` for a skipped turn or
+//! `f<seat>` for a forfeit.
+
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt::Display,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    rc::Rc,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use crate::{validate_rules, Action, BoardBackend, Game, GameError, Move, Player, Res};
+
+pub mod server;
+
+/// How long a client that connected to a host has to send its `join`.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One step of a game, as it is replayed to clients that join late.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Step {
+    /// A column a piece was dropped in.
+    Move(usize),
+    /// A turn that was skipped.
+    Skip,
+    /// A seat that forfeited.
+    Forfeit(u32),
+}
+impl Step {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "s" => Some(Step::Skip),
+            _ => match s.strip_prefix('f') {
+                Some(seat) => seat.parse().ok().map(Step::Forfeit),
+                None => s.parse().ok().map(Step::Move),
+            },
+        }
+    }
+
+    fn replay(self, game: &mut Game) -> Res<()> {
+        match self {
+            Step::Move(column) => {
+                game.place_piece(column)?;
+                game.end_turn();
+            }
+            Step::Skip => game.end_turn(),
+            Step::Forfeit(seat) => game.forfeit(seat)?,
+        }
+        Ok(())
+    }
+}
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Move(column) => write!(f, "{}", column),
+            Step::Skip => write!(f, "s"),
+            Step::Forfeit(seat) => write!(f, "f{}", seat),
+        }
+    }
+}
+
+/// Something the server did to a seat whose player left. `HostFeed` returns it as an error
+/// instead of a column, the front end shows it and `apply`s it to its copy of the game.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SeatEvent {
+    /// The player left during their turn and has `seconds` to rejoin.
+    Waiting {
+        /// The seat that left.
+        seat: u32,
+        /// Time left to rejoin.
+        seconds: u64,
+    },
+    /// The player's turn was skipped.
+    Skipped(u32),
+    /// The player was out of time and forfeited.
+    Forfeited(u32),
+}
+impl SeatEvent {
+    /// Plays the event on the client's copy of the game.
+    pub fn apply(self, game: &mut Game) -> Res<()> {
+        match self {
+            SeatEvent::Waiting { .. } => Ok(()),
+            SeatEvent::Skipped(_) => Step::Skip.replay(game),
+            SeatEvent::Forfeited(seat) => Step::Forfeit(seat).replay(game),
+        }
+    }
+}
+impl Display for SeatEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeatEvent::Waiting { seat, seconds } => write!(
+                f,
+                "Player {} left, waiting up to {} seconds for them to rejoin",
+                seat + 1,
+                seconds
+            ),
+            SeatEvent::Skipped(seat) => {
+                write!(f, "Player {} is away, their turn is skipped", seat + 1)
+            }
+            SeatEvent::Forfeited(seat) => {
+                write!(f, "Player {} did not come back and forfeits", seat + 1)
+            }
+        }
+    }
+}
+impl Error for SeatEvent {}
+
+/// A lobby as the server lists it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LobbyInfo {
+    /// Id to enter the lobby with.
+    pub id: u32,
+    /// Width of the board.
+    pub width: usize,
+    /// Height of the board.
+    pub height: usize,
+    /// Pieces in a row needed to win.
+    pub connect_size: usize,
+    /// Number of players.
+    pub player_count: u32,
+    /// Seats nobody took yet.
+    pub free_seats: u32,
+}
+
+/// One line of the protocol.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    /// `join [seat]`
+    Join(Option<u32>),
+    /// `welcome <seat> <width> <height> <connect> <players>`
+    Welcome {
+        /// The seat the client plays.
+        seat: u32,
+        /// Width of the board.
+        width: usize,
+        /// Height of the board.
+        height: usize,
+        /// Pieces in a row needed to win.
+        connect_size: usize,
+        /// Number of players.
+        player_count: u32,
+    },
+    /// `history <step>...`
+    History(Vec<Step>),
+    /// `start`
+    Start,
+    /// `play <column>`
+    Play(usize),
+    /// `moved <seat> <column>`
+    Moved {
+        /// The seat that moved.
+        seat: u32,
+        /// The column of the move.
+        column: usize,
+    },
+    /// `error <message>`
+    Error(String),
+    /// `list`
+    List,
+    /// `lobby <id> <width> <height> <connect> <players> <free seats>`
+    Lobby(LobbyInfo),
+    /// `listed`
+    Listed,
+    /// `create <width> <height> <connect> <players>`
+    Create {
+        /// Width of the board.
+        width: usize,
+        /// Height of the board.
+        height: usize,
+        /// Pieces in a row needed to win.
+        connect_size: usize,
+        /// Number of players.
+        player_count: u32,
+    },
+    /// `created <id>`
+    Created(u32),
+    /// `enter <id> [seat]`
+    Enter {
+        /// The id of the lobby.
+        lobby: u32,
+        /// The seat to take, the first free one if it is `None`.
+        seat: Option<u32>,
+    },
+    /// `spectate <id>`
+    Spectate(u32),
+    /// `watching <width> <height> <connect> <players>`
+    Watching {
+        /// Width of the board.
+        width: usize,
+        /// Height of the board.
+        height: usize,
+        /// Pieces in a row needed to win.
+        connect_size: usize,
+        /// Number of players.
+        player_count: u32,
+    },
+    /// `session <token>`
+    Session(String),
+    /// `rejoin <id> <token>`
+    Rejoin {
+        /// The id of the lobby.
+        lobby: u32,
+        /// The token of the `session` message.
+        token: String,
+    },
+    /// `waiting <seat> <seconds>`
+    Waiting {
+        /// The seat that left.
+        seat: u32,
+        /// Time left to rejoin.
+        seconds: u64,
+    },
+    /// `skipped <seat>`
+    Skipped(u32),
+    /// `forfeited <seat>`
+    Forfeited(u32),
+}
+impl Message {
+    /// Reads a line of the protocol.
+    pub fn parse(line: &str) -> Res<Self> {
+        let invalid = || format!("Invalid message '{}'", line);
+        let (command, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let words: Vec<&str> = rest.split_whitespace().collect();
+        match (command, &words[..]) {
+            ("error", _) => return Ok(Message::Error(rest.to_string())),
+            ("session", [token]) => return Ok(Message::Session(token.to_string())),
+            ("rejoin", [lobby, token]) => {
+                return Ok(Message::Rejoin {
+                    lobby: lobby.parse().map_err(|_| invalid())?,
+                    token: token.to_string(),
+                })
+            }
+            ("history", steps) => {
+                return steps
+                    .iter()
+                    .map(|step| Step::parse(step).ok_or_else(|| invalid().into()))
+                    .collect::<Res<Vec<Step>>>()
+                    .map(Message::History)
+            }
+            _ => {}
+        }
+        let numbers = rest
+            .split_whitespace()
+            .map(|n| n.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| invalid())?;
+        let message = match (command, &numbers[..]) {
+            ("join", []) => Message::Join(None),
+            ("join", &[seat]) => Message::Join(Some(u32::try_from(seat)?)),
+            ("welcome", &[seat, width, height, connect_size, player_count]) => Message::Welcome {
+                seat: u32::try_from(seat)?,
+                width,
+                height,
+                connect_size,
+                player_count: u32::try_from(player_count)?,
+            },
+            ("start", []) => Message::Start,
+            ("play", &[column]) => Message::Play(column),
+            ("moved", &[seat, column]) => Message::Moved {
+                seat: u32::try_from(seat)?,
+                column,
+            },
+            ("list", []) => Message::List,
+            ("lobby", &[id, width, height, connect_size, player_count, free_seats]) => {
+                Message::Lobby(LobbyInfo {
+                    id: u32::try_from(id)?,
+                    width,
+                    height,
+                    connect_size,
+                    player_count: u32::try_from(player_count)?,
+                    free_seats: u32::try_from(free_seats)?,
+                })
+            }
+            ("listed", []) => Message::Listed,
+            ("create", &[width, height, connect_size, player_count]) => Message::Create {
+                width,
+                height,
+                connect_size,
+                player_count: u32::try_from(player_count)?,
+            },
+            ("created", &[id]) => Message::Created(u32::try_from(id)?),
+            ("enter", &[lobby]) => Message::Enter {
+                lobby: u32::try_from(lobby)?,
+                seat: None,
+            },
+            ("enter", &[lobby, seat]) => Message::Enter {
+                lobby: u32::try_from(lobby)?,
+                seat: Some(u32::try_from(seat)?),
+            },
+            ("spectate", &[lobby]) => Message::Spectate(u32::try_from(lobby)?),
+            ("watching", &[width, height, connect_size, player_count]) => Message::Watching {
+                width,
+                height,
+                connect_size,
+                player_count: u32::try_from(player_count)?,
+            },
+            ("waiting", &[seat, seconds]) => Message::Waiting {
+                seat: u32::try_from(seat)?,
+                seconds: seconds as u64,
+            },
+            ("skipped", &[seat]) => Message::Skipped(u32::try_from(seat)?),
+            ("forfeited", &[seat]) => Message::Forfeited(u32::try_from(seat)?),
+            _ => return Err(invalid().into()),
+        };
+        Ok(message)
+    }
+}
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::Join(None) => write!(f, "join"),
+            Message::Join(Some(seat)) => write!(f, "join {}", seat),
+            Message::Welcome {
+                seat,
+                width,
+                height,
+                connect_size,
+                player_count,
+            } => write!(
+                f,
+                "welcome {} {} {} {} {}",
+                seat, width, height, connect_size, player_count
+            ),
+            Message::History(steps) => {
+                write!(f, "history")?;
+                for step in steps {
+                    write!(f, " {}", step)?;
+                }
+                Ok(())
+            }
+            Message::Start => write!(f, "start"),
+            Message::Play(column) => write!(f, "play {}", column),
+            Message::Moved { seat, column } => write!(f, "moved {} {}", seat, column),
+            Message::Error(message) => write!(f, "error {}", message),
+            Message::List => write!(f, "list"),
+            Message::Lobby(lobby) => write!(
+                f,
+                "lobby {} {} {} {} {} {}",
+                lobby.id,
+                lobby.width,
+                lobby.height,
+                lobby.connect_size,
+                lobby.player_count,
+                lobby.free_seats
+            ),
+            Message::Listed => write!(f, "listed"),
+            Message::Create {
+                width,
+                height,
+                connect_size,
+                player_count,
+            } => write!(
+                f,
+                "create {} {} {} {}",
+                width, height, connect_size, player_count
+            ),
+            Message::Created(id) => write!(f, "created {}", id),
+            Message::Enter { lobby, seat: None } => write!(f, "enter {}", lobby),
+            Message::Enter {
+                lobby,
+                seat: Some(seat),
+            } => write!(f, "enter {} {}", lobby, seat),
+            Message::Spectate(lobby) => write!(f, "spectate {}", lobby),
+            Message::Watching {
+                width,
+                height,
+                connect_size,
+                player_count,
+            } => write!(
+                f,
+                "watching {} {} {} {}",
+                width, height, connect_size, player_count
+            ),
+            Message::Session(token) => write!(f, "session {}", token),
+            Message::Rejoin { lobby, token } => write!(f, "rejoin {} {}", lobby, token),
+            Message::Waiting { seat, seconds } => write!(f, "waiting {} {}", seat, seconds),
+            Message::Skipped(seat) => write!(f, "skipped {}", seat),
+            Message::Forfeited(seat) => write!(f, "forfeited {}", seat),
+        }
+    }
+}
+
+/// A TCP stream that is read on a background thread, so the GUI can check for new lines
+/// without blocking.
+pub struct Connection {
+    stream: TcpStream,
+    lines: Receiver<String>,
+    peeked: RefCell<Option<String>>,
+}
+impl Connection {
+    /// Starts reading `stream` on a background thread.
+    pub fn new(stream: TcpStream) -> Res<Self> {
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else { return };
+                if sender.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Connection {
+            stream,
+            lines,
+            peeked: RefCell::new(None),
+        })
+    }
+
+    /// Connects to `address`.
+    pub fn connect(address: impl ToSocketAddrs) -> Res<Self> {
+        Connection::new(TcpStream::connect(address)?)
+    }
+
+    /// Sends `message` as one line.
+    pub fn send(&self, message: &Message) -> Res<()> {
+        writeln!(&self.stream, "{}", message)?;
+        Ok(())
+    }
+
+    /// Whether a message arrived that `receive` can return right away.
+    pub fn has_message(&self) -> bool {
+        let mut peeked = self.peeked.borrow_mut();
+        if peeked.is_none() {
+            match self.lines.try_recv() {
+                Ok(line) => *peeked = Some(line),
+                // A closed connection is reported by `receive`
+                Err(TryRecvError::Disconnected) => return true,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+        peeked.is_some()
+    }
+
+    /// Waits for the next message.
+    pub fn receive(&self) -> Res<Message> {
+        let line = match self.peeked.borrow_mut().take() {
+            Some(line) => line,
+            None => self.lines.recv().map_err(|_| "The connection was closed")?,
+        };
+        Message::parse(&line)
+    }
+
+    /// Like `receive`, but gives up once nothing arrived within `timeout`.
+    pub fn receive_timeout(&self, timeout: Duration) -> Res<Message> {
+        let line = match self.peeked.borrow_mut().take() {
+            Some(line) => line,
+            None => self.lines.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => "The connection sent nothing in time",
+                RecvTimeoutError::Disconnected => "The connection was closed",
+            })?,
+        };
+        Message::parse(&line)
+    }
+}
+
+/// Waits on `address` until a client joined for every seat in `remote_seats`, then starts the
+/// game. Returns the players for those seats in the order of `remote_seats`.
+pub fn host(
+    address: impl ToSocketAddrs,
+    game: &Game,
+    remote_seats: &[u32],
+) -> Res<Vec<RemotePlayer>> {
+    accept_players(&TcpListener::bind(address)?, game, remote_seats)
+}
+
+fn accept_players(
+    listener: &TcpListener,
+    game: &Game,
+    remote_seats: &[u32],
+) -> Res<Vec<RemotePlayer>> {
+    let mut players: Vec<Option<RemotePlayer>> = remote_seats.iter().map(|_| None).collect();
+    while players.iter().any(Option::is_none) {
+        let connection = Connection::new(listener.accept()?.0)?;
+        let free = |seat: u32| {
+            remote_seats
+                .iter()
+                .position(|&s| s == seat)
+                .filter(|&i| players[i].is_none())
+        };
+        // A client that does not join in time would keep everybody else out
+        let index = match connection.receive_timeout(JOIN_TIMEOUT) {
+            Ok(Message::Join(None)) => players.iter().position(Option::is_none),
+            Ok(Message::Join(Some(seat))) => free(seat),
+            _ => {
+                let _ = connection.send(&Message::Error("Expected a join".to_string()));
+                continue;
+            }
+        };
+        let Some(index) = index else {
+            let _ = connection.send(&Message::Error("That seat is not free".to_string()));
+            continue;
+        };
+
+        let seat = remote_seats[index];
+        // A client that left again just frees the seat
+        if connection.send(&welcome(game, Some(seat))).is_ok()
+            && connection.send(&history(game)).is_ok()
+        {
+            players[index] = Some(RemotePlayer { connection, seat });
+        }
+    }
+
+    let players: Vec<RemotePlayer> = players.into_iter().flatten().collect();
+    for player in &players {
+        player.connection.send(&Message::Start)?;
+    }
+    Ok(players)
+}
+
+/// Joins the game hosted at `address`, on `seat` or the first free one. Returns the game as far
+/// as it has been played, the seat that was claimed and the connection to the host once the game
+/// starts.
+pub fn join(address: impl ToSocketAddrs, seat: Option<u32>) -> Res<(Game, u32, Rc<Connection>)> {
+    let connection = Connection::connect(address)?;
+    connection.send(&Message::Join(seat))?;
+    let (game, seat, _) = receive_game(&connection)?;
+    let seat = seat.ok_or("The host let us watch instead of play")?;
+    Ok((game, seat, Rc::new(connection)))
+}
+
+/// Lists the lobbies of the server at `address`.
+pub fn list_lobbies(address: impl ToSocketAddrs) -> Res<Vec<LobbyInfo>> {
+    let connection = Connection::connect(address)?;
+    connection.send(&Message::List)?;
+    let mut lobbies = Vec::new();
+    loop {
+        match connection.receive()? {
+            Message::Lobby(lobby) => lobbies.push(lobby),
+            Message::Listed => return Ok(lobbies),
+            message => return Err(format!("Expected a lobby, got '{}'", message).into()),
+        }
+    }
+}
+
+/// Creates a lobby with these rules on the server at `address` and returns its id.
+pub fn create_lobby(
+    address: impl ToSocketAddrs,
+    player_count: u32,
+    connect_size: usize,
+    width: usize,
+    height: usize,
+) -> Res<u32> {
+    let connection = Connection::connect(address)?;
+    connection.send(&Message::Create {
+        width,
+        height,
+        connect_size,
+        player_count,
+    })?;
+    match connection.receive()? {
+        Message::Created(id) => Ok(id),
+        Message::Error(message) => Err(message.into()),
+        message => Err(format!("Expected the lobby id, got '{}'", message).into()),
+    }
+}
+
+/// Takes `seat`, or the first free one, in a lobby of the server at `address`. Returns the same
+/// as `join` and the session token to rejoin with.
+pub fn enter_lobby(
+    address: impl ToSocketAddrs,
+    lobby: u32,
+    seat: Option<u32>,
+) -> Res<(Game, u32, Rc<Connection>, String)> {
+    take_seat(address, Message::Enter { lobby, seat })
+}
+
+/// Takes back the seat of the session `token` in a lobby of the server at `address`. Returns
+/// the same as `enter_lobby`.
+pub fn rejoin_lobby(
+    address: impl ToSocketAddrs,
+    lobby: u32,
+    token: String,
+) -> Res<(Game, u32, Rc<Connection>, String)> {
+    take_seat(address, Message::Rejoin { lobby, token })
+}
+
+fn take_seat(
+    address: impl ToSocketAddrs,
+    request: Message,
+) -> Res<(Game, u32, Rc<Connection>, String)> {
+    let connection = Connection::connect(address)?;
+    connection.send(&request)?;
+    match receive_game(&connection)? {
+        (game, Some(seat), Some(token)) => Ok((game, seat, Rc::new(connection), token)),
+        _ => Err("The server did not give us a seat and session".into()),
+    }
+}
+
+/// Watches a lobby of the server at `address`. Every move arrives as `moved`, so each seat can
+/// be played by a `HostFeed`.
+pub fn spectate(address: impl ToSocketAddrs, lobby: u32) -> Res<(Game, Rc<Connection>)> {
+    let connection = Connection::connect(address)?;
+    connection.send(&Message::Spectate(lobby))?;
+    let (game, _, _) = receive_game(&connection)?;
+    Ok((game, Rc::new(connection)))
+}
+
+/// The first message a client gets, `Watching` for spectators.
+fn welcome(game: &Game, seat: Option<u32>) -> Message {
+    let (width, height) = (game.board().width(), game.board().height());
+    let (connect_size, player_count) = (game.connect_size(), game.player_count());
+    match seat {
+        Some(seat) => Message::Welcome {
+            seat,
+            width,
+            height,
+            connect_size,
+            player_count,
+        },
+        None => Message::Watching {
+            width,
+            height,
+            connect_size,
+            player_count,
+        },
+    }
+}
+
+/// The moves played so far.
+fn history(game: &Game) -> Message {
+    Message::History(game.history().iter().map(|m| Step::Move(m.x)).collect())
+}
+
+/// Reads the welcome, the session, the history and the start of a game. The seat is `None` for
+/// spectators, the session token is only sent by servers.
+fn receive_game(connection: &Connection) -> Res<(Game, Option<u32>, Option<String>)> {
+    let (seat, player_count, connect_size, width, height) = match connection.receive()? {
+        Message::Welcome {
+            seat,
+            width,
+            height,
+            connect_size,
+            player_count,
+        } => (Some(seat), player_count, connect_size, width, height),
+        Message::Watching {
+            width,
+            height,
+            connect_size,
+            player_count,
+        } => (None, player_count, connect_size, width, height),
+        Message::Error(message) => return Err(message.into()),
+        message => return Err(format!("Expected a welcome, got '{}'", message).into()),
+    };
+    validate_rules(player_count, connect_size, width, height)?;
+    let mut game = Game::new(player_count, connect_size, width, height);
+    let mut message = connection.receive()?;
+    let mut token = None;
+    if let Message::Session(session) = message {
+        token = Some(session);
+        message = connection.receive()?;
+    }
+    match message {
+        Message::History(steps) => {
+            for step in steps {
+                step.replay(&mut game)?;
+            }
+        }
+        message => return Err(format!("Expected the history, got '{}'", message).into()),
+    }
+    match connection.receive()? {
+        Message::Start => Ok((game, seat, token)),
+        message => Err(format!("Expected the start, got '{}'", message).into()),
+    }
+}
+
+/// On the host, a seat that is played by a client. It passes every move on to that client.
+pub struct RemotePlayer {
+    connection: Connection,
+    seat: u32,
+}
+impl RemotePlayer {
+    /// The seat the client plays.
+    pub fn seat(&self) -> u32 {
+        self.seat
+    }
+}
+impl Player for RemotePlayer {
+    fn choose_action(&mut self, _game: &Game) -> Res<Action> {
+        match self.connection.receive()? {
+            Message::Play(column) => Ok(Action::Place(column)),
+            message => Err(format!(
+                "Player {} sent '{}' instead of a move",
+                self.seat + 1,
+                message
+            )
+            .into()),
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.connection.has_message()
+    }
+
+    fn move_accepted(&mut self, game: &Game, last_move: Move) {
+        self.opponent_moved(game, last_move)
+    }
+
+    fn move_rejected(&mut self, _game: &Game, error: &GameError) {
+        let _ = self.connection.send(&Message::Error(error.to_string()));
+    }
+
+    fn opponent_moved(&mut self, _game: &Game, last_move: Move) {
+        // A client that left shows up when it is their turn
+        let _ = self.connection.send(&Message::Moved {
+            seat: last_move.player,
+            column: last_move.x,
+        });
+    }
+}
+
+/// On a client, a seat that is played somewhere else. Its moves are read from the host.
+pub struct HostFeed {
+    connection: Rc<Connection>,
+    seat: u32,
+}
+impl HostFeed {
+    /// Reads the moves of `seat` from `connection`.
+    pub fn new(connection: Rc<Connection>, seat: u32) -> Self {
+        HostFeed { connection, seat }
+    }
+}
+impl Player for HostFeed {
+    fn choose_action(&mut self, _game: &Game) -> Res<Action> {
+        let event = match self.connection.receive()? {
+            Message::Moved { seat, column } if seat == self.seat => {
+                return Ok(Action::Place(column))
+            }
+            Message::Waiting { seat, seconds } if seat == self.seat => {
+                SeatEvent::Waiting { seat, seconds }
+            }
+            Message::Skipped(seat) if seat == self.seat => SeatEvent::Skipped(seat),
+            Message::Forfeited(seat) if seat == self.seat => SeatEvent::Forfeited(seat),
+            message => {
+                return Err(format!(
+                    "Expected a move of player {} from the host, got '{}'",
+                    self.seat + 1,
+                    message
+                )
+                .into())
+            }
+        };
+        Err(Box::new(event))
+    }
+
+    fn is_ready(&self) -> bool {
+        self.connection.has_message()
+    }
+}
+
+/// On a client, the seat played here. The moves of `player` are sent to the host and only
+/// played once the host accepted them.
+pub struct Relay {
+    player: Box<dyn Player>,
+    connection: Rc<Connection>,
+    seat: u32,
+}
+impl Relay {
+    /// Plays `seat` with `player` and sends its moves over `connection`.
+    pub fn new(player: Box<dyn Player>, connection: Rc<Connection>, seat: u32) -> Self {
+        Relay {
+            player,
+            connection,
+            seat,
+        }
+    }
+}
+impl Player for Relay {
+    fn choose_action(&mut self, game: &Game) -> Res<Action> {
+        // The protocol only knows dropped pieces, other moves are rejected right here
+        let column = self.player.choose_column(game)?;
+        // Moves the host would reject anyway are asked for again right here
+        game.clone().place_piece(column)?;
+        self.connection.send(&Message::Play(column))?;
+        match self.connection.receive()? {
+            Message::Moved { seat, column } if seat == self.seat => Ok(Action::Place(column)),
+            Message::Error(message) => {
+                Err(format!("The host rejected the move: {}", message).into())
+            }
+            message => {
+                Err(format!("Expected the host to confirm the move, got '{}'", message).into())
+            }
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.player.is_ready()
+    }
+
+    fn move_accepted(&mut self, game: &Game, last_move: Move) {
+        self.player.move_accepted(game, last_move)
+    }
+
+    fn move_rejected(&mut self, game: &Game, error: &GameError) {
+        self.player.move_rejected(game, error)
+    }
+
+    fn opponent_moved(&mut self, game: &Game, last_move: Move) {
+        self.player.opponent_moved(game, last_move)
+    }
+
+    fn game_ended(&mut self, game: &Game) {
+        self.player.game_ended(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{play_turn, ScriptedPlayer};
+
+    #[test]
+    fn hosted_game_is_played_the_same_on_both_sides() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let (mut game, seat, connection) = join(address, None).unwrap();
+            assert_eq!(seat, 1);
+            let mut players: Vec<Box<dyn Player>> = vec![
+                Box::new(HostFeed::new(connection.clone(), 0)),
+                Box::new(Relay::new(
+                    Box::new(ScriptedPlayer::new([1, 1, 1])),
+                    connection,
+                    seat,
+                )),
+            ];
+            while !game.is_over() {
+                play_turn(&mut game, &mut players).unwrap();
+            }
+            game.to_notation()
+        });
+
+        let mut game = Game::new(2, 4, 7, 6);
+        let remote = accept_players(&listener, &game, &[1]).unwrap().remove(0);
+        let mut players: Vec<Box<dyn Player>> = vec![
+            Box::new(ScriptedPlayer::new([0, 0, 0, 0])),
+            Box::new(remote),
+        ];
+        while !game.is_over() {
+            play_turn(&mut game, &mut players).unwrap();
+        }
+
+        assert_eq!(game.winner(), Some(0));
+        assert_eq!(client.join().unwrap(), game.to_notation());
+    }
+}
@@ -0,0 +1,473 @@
+//! A headless server that runs many games at once as lobbies. It owns every game, so clients
+//! can only play their own seat and only when it is their turn.
+
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    hash::{BuildHasher, Hasher},
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    net::{welcome, LobbyInfo, Message, Step},
+    take_turn, validate_rules, BoardBackend, Game, Res,
+};
+
+/// How long a client may keep a message waiting before it is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What happens to a player that left and did not rejoin in time.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TimeoutPolicy {
+    /// The player is out of the game and the others play on.
+    Forfeit,
+    /// The player's turns are skipped until they rejoin.
+    Skip,
+}
+
+/// How the server treats players that left.
+#[derive(Clone, Copy, Debug)]
+pub struct ServerConfig {
+    /// How long the game waits for a player that left when it is their turn.
+    pub timeout: Duration,
+    /// What happens once a player did not rejoin within `timeout`.
+    pub policy: TimeoutPolicy,
+}
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            timeout: Duration::from_secs(60),
+            policy: TimeoutPolicy::Forfeit,
+        }
+    }
+}
+
+struct Seat {
+    /// The client playing the seat, so a stale connection can not free it after a rejoin.
+    client: u64,
+    /// `None` while the player is away.
+    outbox: Option<Outbox>,
+    token: String,
+    left_at: Instant,
+}
+
+struct Lobby {
+    game: Game,
+    /// Everything that happened in the game, sent to clients that enter late.
+    steps: Vec<Step>,
+    seats: Vec<Option<Seat>>,
+    spectators: Vec<(u64, Outbox)>,
+    /// Set once every seat was taken.
+    started: bool,
+    /// Set once somebody entered, before that the lobby is kept for a timeout after it was
+    /// created, since its creator takes a seat on another connection.
+    visited: bool,
+    created_at: Instant,
+}
+impl Lobby {
+    fn new(game: Game) -> Self {
+        Lobby {
+            seats: (0..game.player_count()).map(|_| None).collect(),
+            game,
+            steps: Vec::new(),
+            spectators: Vec::new(),
+            started: false,
+            visited: false,
+            created_at: Instant::now(),
+        }
+    }
+
+    fn info(&self, id: u32) -> LobbyInfo {
+        LobbyInfo {
+            id,
+            width: self.game.board().width(),
+            height: self.game.board().height(),
+            connect_size: self.game.connect_size(),
+            player_count: self.game.player_count(),
+            free_seats: self.seats.iter().filter(|seat| seat.is_none()).count() as u32,
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.started && !self.game.is_over()
+    }
+
+    /// Whether nobody is connected anymore, so the lobby can be closed. A running game is kept
+    /// until every player that left ran out of time to rejoin, with `TimeoutPolicy::Forfeit`
+    /// they have forfeited by then.
+    fn is_abandoned(&self, config: &ServerConfig) -> bool {
+        if !self.spectators.is_empty() || self.has_connected_seat() {
+            return false;
+        }
+        if self.is_running() {
+            return self
+                .seats
+                .iter()
+                .flatten()
+                .all(|seat| seat.left_at.elapsed() >= config.timeout);
+        }
+        self.visited || self.created_at.elapsed() >= config.timeout
+    }
+
+    fn has_connected_seat(&self) -> bool {
+        self.seats
+            .iter()
+            .flatten()
+            .any(|seat| seat.outbox.is_some())
+    }
+
+    /// The active seat and when its player left, if they are away.
+    fn away_turn(&self) -> Option<(u32, Instant)> {
+        let seat = self.game.active_player();
+        match &self.seats[seat as usize] {
+            Some(away) if self.is_running() && away.outbox.is_none() => Some((seat, away.left_at)),
+            _ => None,
+        }
+    }
+
+    /// Sends `message` to every seat and spectator. Clients that left are dropped later by their
+    /// own thread.
+    fn broadcast(&self, message: &Message) {
+        let outboxes = self
+            .seats
+            .iter()
+            .flatten()
+            .filter_map(|seat| seat.outbox.as_ref());
+        for outbox in outboxes.chain(self.spectators.iter().map(|(_, outbox)| outbox)) {
+            outbox.send(message);
+        }
+    }
+
+    fn start_if_full(&mut self) {
+        if !self.started && self.seats.iter().all(Option::is_some) {
+            self.started = true;
+            self.broadcast(&Message::Start);
+        }
+    }
+
+    /// Tells everybody that the game waits for the active player to rejoin.
+    fn announce_turn(&self, config: &ServerConfig) {
+        if let Some((seat, left_at)) = self.away_turn() {
+            let remaining = config.timeout.saturating_sub(left_at.elapsed());
+            if !remaining.is_zero() {
+                self.broadcast(&Message::Waiting {
+                    seat,
+                    seconds: remaining.as_secs_f64().ceil() as u64,
+                });
+            }
+        }
+    }
+
+    /// Applies the timeout policy while the active player is away for longer than the timeout,
+    /// at most once per seat so the lock is never held for more than a round.
+    fn enforce_timeout(&mut self, config: &ServerConfig) {
+        // Skipping around a table where nobody is left would never reach a player
+        if config.policy == TimeoutPolicy::Skip && !self.has_connected_seat() {
+            return;
+        }
+        for _ in 0..self.game.player_count() {
+            let Some((seat, left_at)) = self.away_turn() else {
+                return;
+            };
+            if left_at.elapsed() < config.timeout {
+                return;
+            }
+            let (step, message) = match config.policy {
+                TimeoutPolicy::Skip => (Step::Skip, Message::Skipped(seat)),
+                TimeoutPolicy::Forfeit => (Step::Forfeit(seat), Message::Forfeited(seat)),
+            };
+            if step.replay(&mut self.game).is_err() {
+                return;
+            }
+            self.steps.push(step);
+            self.broadcast(&message);
+            self.announce_turn(config);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Lobbies {
+    lobbies: BTreeMap<u32, Lobby>,
+    next_id: u32,
+    next_client: u64,
+}
+
+/// What a client does in the lobby it entered.
+#[derive(Clone, Copy)]
+enum Role {
+    Seat(u32),
+    Spectator,
+}
+
+/// Accepts clients on `address` until the listener fails. Every client is served on its own
+/// thread.
+pub fn run(address: impl ToSocketAddrs, config: ServerConfig) -> Res<()> {
+    let listener = TcpListener::bind(address)?;
+    let lobbies = Arc::new(Mutex::new(Lobbies::default()));
+
+    let watched = lobbies.clone();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(200));
+        let Ok(mut lobbies) = watched.lock() else {
+            return;
+        };
+        for lobby in lobbies.lobbies.values_mut() {
+            lobby.enforce_timeout(&config);
+        }
+        lobbies
+            .lobbies
+            .retain(|_, lobby| !lobby.is_abandoned(&config));
+    });
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let lobbies = lobbies.clone();
+        thread::spawn(move || {
+            let _ = serve(stream, &lobbies, &config);
+        });
+    }
+    Ok(())
+}
+
+/// Messages to one client, written on a thread of its own. A client that stops reading only
+/// holds up itself, and nothing is written to a socket while the lobbies are locked.
+#[derive(Clone)]
+struct Outbox(Sender<Message>);
+impl Outbox {
+    fn new(mut stream: TcpStream) -> Self {
+        let (sender, messages) = mpsc::channel::<Message>();
+        thread::spawn(move || {
+            let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+            for message in messages {
+                if stream
+                    .write_all(format!("{}\n", message).as_bytes())
+                    .is_err()
+                {
+                    // The reading thread sees the connection end and leaves the lobby
+                    let _ = stream.shutdown(Shutdown::Both);
+                    return;
+                }
+            }
+        });
+        Outbox(sender)
+    }
+
+    /// Queues `message`, a client that left just never gets it.
+    fn send(&self, message: &Message) {
+        let _ = self.0.send(message.clone());
+    }
+}
+
+/// A token that is hard to guess, so only the player that left can take their seat back.
+fn new_token(client: u64) -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(client);
+    if let Ok(time) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(time.as_nanos());
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// Sends everything a client needs to play `seat` in `lobby`.
+fn send_seat(outbox: &Outbox, lobby: &Lobby, seat: u32) {
+    outbox.send(&welcome(&lobby.game, Some(seat)));
+    if let Some(taken) = &lobby.seats[seat as usize] {
+        outbox.send(&Message::Session(taken.token.clone()));
+    }
+    outbox.send(&Message::History(lobby.steps.clone()));
+    if lobby.started {
+        outbox.send(&Message::Start);
+    }
+}
+
+fn serve(stream: TcpStream, lobbies: &Mutex<Lobbies>, config: &ServerConfig) -> Res<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let outbox = Outbox::new(stream);
+    let client = {
+        let mut lobbies = lobbies.lock().map_err(|_| "The server state is poisoned")?;
+        lobbies.next_client += 1;
+        lobbies.next_client
+    };
+    let mut entered: Option<(u32, Role)> = None;
+    let result = (|| -> Res<()> {
+        for line in reader.lines() {
+            let line = line?;
+            let message = match Message::parse(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    outbox.send(&Message::Error(e.to_string()));
+                    continue;
+                }
+            };
+            if let Message::Create {
+                width,
+                height,
+                connect_size,
+                player_count,
+            } = message
+            {
+                // The board is built before the lock is taken, every lobby waits for it
+                let reply = match validate_rules(player_count, connect_size, width, height) {
+                    Ok(()) => {
+                        let lobby =
+                            Lobby::new(Game::new(player_count, connect_size, width, height));
+                        let mut lobbies =
+                            lobbies.lock().map_err(|_| "The server state is poisoned")?;
+                        let id = lobbies.next_id;
+                        lobbies.next_id += 1;
+                        lobbies.lobbies.insert(id, lobby);
+                        Message::Created(id)
+                    }
+                    Err(e) => Message::Error(e.to_string()),
+                };
+                outbox.send(&reply);
+                continue;
+            }
+            let mut lobbies = lobbies.lock().map_err(|_| "The server state is poisoned")?;
+            let reply = match (message, entered) {
+                (Message::List, _) => {
+                    for (&id, lobby) in &lobbies.lobbies {
+                        outbox.send(&Message::Lobby(lobby.info(id)));
+                    }
+                    Message::Listed
+                }
+                (Message::Enter { lobby: id, seat }, None) => {
+                    let Some(lobby) = lobbies.lobbies.get_mut(&id) else {
+                        outbox.send(&Message::Error(format!("There is no lobby {}", id)));
+                        continue;
+                    };
+                    let free = match seat {
+                        Some(seat) => Some(seat).filter(|&seat| {
+                            lobby.seats.get(seat as usize).is_some_and(Option::is_none)
+                        }),
+                        None => lobby
+                            .seats
+                            .iter()
+                            .position(Option::is_none)
+                            .map(|i| i as u32),
+                    };
+                    let Some(seat) = free else {
+                        outbox.send(&Message::Error("That seat is not free".to_string()));
+                        continue;
+                    };
+                    lobby.seats[seat as usize] = Some(Seat {
+                        client,
+                        outbox: Some(outbox.clone()),
+                        token: new_token(client),
+                        left_at: Instant::now(),
+                    });
+                    send_seat(&outbox, lobby, seat);
+                    entered = Some((id, Role::Seat(seat)));
+                    lobby.visited = true;
+                    lobby.start_if_full();
+                    continue;
+                }
+                (Message::Rejoin { lobby: id, token }, None) => {
+                    let Some(lobby) = lobbies.lobbies.get_mut(&id) else {
+                        outbox.send(&Message::Error(format!("There is no lobby {}", id)));
+                        continue;
+                    };
+                    let away = lobby.seats.iter().position(|seat| {
+                        seat.as_ref()
+                            .is_some_and(|seat| seat.token == token && seat.outbox.is_none())
+                    });
+                    let Some(seat) = away.map(|seat| seat as u32) else {
+                        outbox.send(&Message::Error(
+                            "There is no seat to rejoin with that token".to_string(),
+                        ));
+                        continue;
+                    };
+                    if lobby.game.has_forfeited(seat) {
+                        outbox.send(&Message::Error(
+                            "You already forfeited this game".to_string(),
+                        ));
+                        continue;
+                    }
+                    if let Some(away) = &mut lobby.seats[seat as usize] {
+                        away.client = client;
+                        away.outbox = Some(outbox.clone());
+                    }
+                    send_seat(&outbox, lobby, seat);
+                    entered = Some((id, Role::Seat(seat)));
+                    continue;
+                }
+                (Message::Spectate(id), None) => {
+                    let Some(lobby) = lobbies.lobbies.get_mut(&id) else {
+                        outbox.send(&Message::Error(format!("There is no lobby {}", id)));
+                        continue;
+                    };
+                    outbox.send(&welcome(&lobby.game, None));
+                    outbox.send(&Message::History(lobby.steps.clone()));
+                    if lobby.started {
+                        outbox.send(&Message::Start);
+                    }
+                    lobby.spectators.push((client, outbox.clone()));
+                    entered = Some((id, Role::Spectator));
+                    lobby.visited = true;
+                    continue;
+                }
+                (
+                    Message::Enter { .. } | Message::Rejoin { .. } | Message::Spectate(_),
+                    Some(_),
+                ) => Message::Error("You are already in a lobby".to_string()),
+                (Message::Play(column), Some((id, Role::Seat(seat)))) => {
+                    let lobby = lobbies.lobbies.get_mut(&id).ok_or("The lobby was closed")?;
+                    if !lobby.started {
+                        Message::Error("The game has not started yet".to_string())
+                    } else if lobby.game.active_player() != seat && !lobby.game.is_over() {
+                        Message::Error("It is not your turn".to_string())
+                    } else {
+                        match take_turn(&mut lobby.game, &mut |_: &Game| Ok(column)) {
+                            Ok(_) => {
+                                lobby.steps.push(Step::Move(column));
+                                lobby.broadcast(&Message::Moved { seat, column });
+                                lobby.announce_turn(config);
+                                continue;
+                            }
+                            Err(e) => Message::Error(e.to_string()),
+                        }
+                    }
+                }
+                (Message::Play(_), _) => Message::Error("Only seated players can play".to_string()),
+                (message, _) => Message::Error(format!("Unexpected message '{}'", message)),
+            };
+            outbox.send(&reply);
+        }
+        Ok(())
+    })();
+
+    // Leave the lobby, whether the client quit or the connection broke
+    if let Some((id, role)) = entered {
+        let mut lobbies = lobbies.lock().map_err(|_| "The server state is poisoned")?;
+        if let Some(lobby) = lobbies.lobbies.get_mut(&id) {
+            let running = lobby.is_running();
+            match role {
+                Role::Seat(seat) => {
+                    let taken = &mut lobby.seats[seat as usize];
+                    match taken {
+                        Some(taken) if taken.client != client => {}
+                        // A running game keeps the seat, so its player can rejoin
+                        Some(taken) if running => {
+                            taken.outbox = None;
+                            taken.left_at = Instant::now();
+                        }
+                        _ => *taken = None,
+                    }
+                    lobby.announce_turn(config);
+                }
+                Role::Spectator => lobby.spectators.retain(|(c, _)| *c != client),
+            }
+            if lobby.is_abandoned(config) {
+                lobbies.lobbies.remove(&id);
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,218 @@
+use std::fs;
+
+use crate::{validate_rules_3d, Action, BoardBackend, Game, Move, MoveKind, Res, Topology};
+
+/// Text notation of a game: a header line `width height connect_size player_count` with the
+/// variants `popout` or `free` and `cylinder` or `torus` after it, followed by the played
+/// columns, counted from 1 like the usual Connect Four notation ("4453..."). Pops are written as
+/// `p` and their column. Games without gravity (`free`) write every move as `x,y`. Boards wider
+/// than 9 columns and games without gravity separate the moves with spaces. Boards with blocked
+/// cells list them in a line `blocked x,y ...` between the header and the moves. 3D boards write
+/// their width as `width x depth` ("4x4 4 4 2") and number their pillars row by row from the
+/// front, so pillar (x, z) is column `z * width + x + 1`.
+impl Game {
+    /// Writes the game in the notation, see above.
+    pub fn to_notation(&self) -> String {
+        let moves = format_moves(&self.history, self.board.width, self.gravity);
+        let mut header = format!(
+            "{} {} {} {}",
+            format_width(self.width(), self.depth),
+            self.board.height,
+            self.connect_size,
+            self.player_count
+        );
+        if !self.gravity {
+            header += " free";
+        } else if self.pop_out {
+            header += " popout";
+        }
+        if self.topology != Topology::Flat {
+            header += &format!(" {}", self.topology);
+        }
+        let blocked = self.board.blocked_cells();
+        if !blocked.is_empty() {
+            header += &format!("\nblocked {}", format_cells(&blocked));
+        }
+        format!("{}\n{}\n", header, moves)
+    }
+
+    /// Rebuilds a game by replaying the moves of the notation.
+    pub fn from_notation(notation: &str) -> Res<Game> {
+        let mut lines = notation
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .peekable();
+        let header = lines.next().ok_or("The notation is empty")?;
+        let words: Vec<&str> = header.split_whitespace().collect();
+        let (numbers, variants) = words.split_at(words.len().min(4));
+        let (mut pop_out, mut gravity, mut topology) = (false, true, Topology::Flat);
+        for &variant in variants {
+            match variant {
+                "popout" => pop_out = true,
+                "free" => gravity = false,
+                _ => {
+                    topology = Topology::parse(variant)
+                        .ok_or_else(|| format!("Unknown variant '{}' in the header", variant))?
+                }
+            }
+        }
+        let [width, numbers @ ..] = numbers else {
+            return Err("The header needs width, height, connect size and player count".into());
+        };
+        let invalid = || format!("Invalid header '{}'", header);
+        let (width, depth) = parse_width(width).ok_or_else(invalid)?;
+        let numbers = numbers
+            .iter()
+            .map(|n| n.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| invalid())?;
+        let [board_height, connect_size, player_count] = numbers[..] else {
+            return Err(format!(
+                "The header '{}' needs width, height, connect size and player count",
+                header
+            )
+            .into());
+        };
+        let player_count = u32::try_from(player_count)?;
+        validate_rules_3d(player_count, connect_size, width, board_height, depth)?;
+        // The columns of a 3D board are its pillars
+        let board_width = width * depth;
+        let blocked = match lines.next_if(|line| line.starts_with("blocked")) {
+            Some(line) => line["blocked".len()..]
+                .split_whitespace()
+                .map(|token| {
+                    parse_cell(token)
+                        .filter(|&(x, y)| x < board_width && y < board_height)
+                        .ok_or_else(|| format!("Invalid blocked cell '{}'", token).into())
+                })
+                .collect::<Res<Vec<(usize, usize)>>>()?,
+            None => Vec::new(),
+        };
+
+        let moves = lines.collect::<Vec<&str>>().join(" ");
+        let mut game = Game::new(player_count, connect_size, width, board_height)
+            .with_depth(depth)
+            .with_pop_out(pop_out)
+            .with_gravity(gravity)
+            .with_topology(topology)
+            .with_blocked_cells(&blocked);
+        game.replay_actions(&parse_actions(&moves, board_width)?)?;
+        Ok(game)
+    }
+
+    /// Plays `actions` one after the other, as they come from `parse_actions`.
+    pub(crate) fn replay_actions(&mut self, actions: &[Action]) -> Res<()> {
+        for (i, &action) in actions.iter().enumerate() {
+            self.play(action)
+                .map_err(|e| format!("Move {} is invalid: {}", i + 1, e))?;
+            self.end_turn();
+        }
+        Ok(())
+    }
+
+    /// Writes the game to `path` in the notation.
+    pub fn save(&self, path: &str) -> Res<()> {
+        fs::write(path, self.to_notation())?;
+        Ok(())
+    }
+
+    /// Reads a game saved with `save`.
+    pub fn load(path: &str) -> Res<Game> {
+        Game::from_notation(&fs::read_to_string(path)?)
+    }
+}
+
+/// Writes the width of a board, `width x depth` for a 3D board.
+pub(crate) fn format_width(width: usize, depth: usize) -> String {
+    match depth {
+        1 => width.to_string(),
+        _ => format!("{}x{}", width, depth),
+    }
+}
+
+/// Reads a width written by `format_width` as width and depth, a plain width has a depth of 1.
+pub(crate) fn parse_width(token: &str) -> Option<(usize, usize)> {
+    match token.split_once('x') {
+        Some((width, depth)) => Some((width.parse().ok()?, depth.parse().ok()?)),
+        None => Some((token.parse().ok()?, 1)),
+    }
+}
+
+/// Writes moves in the notation, pops get a `p` before their column. Without gravity every move
+/// is written as its cell.
+pub(crate) fn format_moves(moves: &[Move], board_width: usize, gravity: bool) -> String {
+    let separator = if board_width > 9 || !gravity { " " } else { "" };
+    moves
+        .iter()
+        .map(|m| match m.kind {
+            MoveKind::Place if !gravity => format!("{},{}", m.x + 1, m.y + 1),
+            MoveKind::Place => (m.x + 1).to_string(),
+            MoveKind::Pop => format!("p{}", m.x + 1),
+        })
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+/// Writes cells as `x,y`, counted from 1.
+pub(crate) fn format_cells(cells: &[(usize, usize)]) -> String {
+    cells
+        .iter()
+        .map(|(x, y)| format!("{},{}", x + 1, y + 1))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Reads a cell written as `x,y` counted from 1, it is returned counted from 0.
+pub(crate) fn parse_cell(token: &str) -> Option<(usize, usize)> {
+    let (x, y) = token.split_once(',')?;
+    match (x.parse::<usize>(), y.parse::<usize>()) {
+        (Ok(x), Ok(y)) if x >= 1 && y >= 1 => Some((x - 1, y - 1)),
+        _ => None,
+    }
+}
+
+/// Reads the moves of the notation, with columns and rows counted from 0.
+pub(crate) fn parse_actions(moves: &str, board_width: usize) -> Res<Vec<Action>> {
+    let tokens: Vec<String> = if board_width > 9 || moves.contains(',') {
+        moves.split_whitespace().map(str::to_string).collect()
+    } else {
+        // One digit per move, a `p` belongs to the digit after it
+        let mut tokens: Vec<String> = Vec::new();
+        let mut pop = false;
+        for c in moves.chars().filter(|c| !c.is_whitespace()) {
+            match (pop, c) {
+                (false, 'p') => pop = true,
+                (true, _) => {
+                    tokens.push(format!("p{}", c));
+                    pop = false;
+                }
+                _ => tokens.push(c.to_string()),
+            }
+        }
+        if pop {
+            tokens.push("p".to_string());
+        }
+        tokens
+    };
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            if token.contains(',') {
+                // The game checks the row, it knows the height
+                return match parse_cell(token) {
+                    Some((x, y)) if x < board_width => Ok(Action::PlaceAt(x, y)),
+                    _ => Err(format!("Invalid cell '{}' in move {}", token, i + 1).into()),
+                };
+            }
+            let (action, column): (fn(usize) -> Action, &str) = match token.strip_prefix('p') {
+                Some(column) => (Action::Pop, column),
+                None => (Action::Place, token),
+            };
+            match column.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= board_width => Ok(action(n - 1)),
+                _ => Err(format!("Invalid column '{}' in move {}", token, i + 1).into()),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+
+use crate::{take_action, Action, Game, GameError, Move, Res, TurnOutcome};
+
+/// Someone or something that plays a seat. Front ends hold one player per seat, so humans, bots
+/// and scripts can be mixed freely.
+pub trait Player {
+    /// Picks what to do on the turn.
+    fn choose_action(&mut self, game: &Game) -> Res<Action>;
+
+    /// Picks the column to place the active player's piece in, for callers that can only drop
+    /// pieces. Pops and cells are rejected with the error the rules would give.
+    fn choose_column(&mut self, game: &Game) -> Res<usize> {
+        match self.choose_action(game)? {
+            Action::Place(x) => Ok(x),
+            Action::Pop(_) => Err(GameError::PopOutDisabled.into()),
+            Action::PlaceAt(..) => Err(GameError::GravityEnabled.into()),
+        }
+    }
+
+    /// Whether `choose_action` can answer right away. Event driven front ends only ask players
+    /// that are ready, so a human can take their time without blocking the window.
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    /// Called after the action this player chose was played.
+    fn move_accepted(&mut self, _game: &Game, _last_move: Move) {}
+
+    /// Called when the column this player chose breaks the rules. They are asked again.
+    fn move_rejected(&mut self, _game: &Game, _error: &GameError) {}
+
+    /// Called after another seat placed or popped a piece.
+    fn opponent_moved(&mut self, _game: &Game, _last_move: Move) {}
+
+    /// Called once the game is won or drawn.
+    fn game_ended(&mut self, _game: &Game) {}
+}
+
+/// Plays a fixed list of columns and fails once it runs out of them.
+pub struct ScriptedPlayer {
+    columns: VecDeque<usize>,
+}
+impl ScriptedPlayer {
+    /// A player that plays `columns` in this order.
+    pub fn new(columns: impl IntoIterator<Item = usize>) -> Self {
+        ScriptedPlayer {
+            columns: columns.into_iter().collect(),
+        }
+    }
+}
+impl Player for ScriptedPlayer {
+    fn choose_action(&mut self, _game: &Game) -> Res<Action> {
+        self.columns
+            .pop_front()
+            .map(Action::Place)
+            .ok_or_else(|| "The script has no moves left".into())
+    }
+}
+
+/// Lets the player of the active seat take their turn and tells everybody else about it.
+pub fn play_turn(game: &mut Game, players: &mut [Box<dyn Player>]) -> Res<TurnOutcome> {
+    let seat = game.active_player() as usize;
+    let player = players
+        .get_mut(seat)
+        .ok_or_else(|| format!("There is no player for seat {}", seat + 1))?;
+    let outcome = match take_action(game, &mut |game: &Game| player.choose_action(game)) {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            if let Some(error) = e.downcast_ref::<GameError>() {
+                player.move_rejected(game, error);
+            }
+            return Err(e);
+        }
+    };
+
+    if let Some(&last_move) = game.history().last() {
+        for (i, player) in players.iter_mut().enumerate() {
+            if i == seat {
+                player.move_accepted(game, last_move);
+            } else {
+                player.opponent_moved(game, last_move);
+            }
+        }
+    }
+    if outcome != TurnOutcome::Continue {
+        for player in players.iter_mut() {
+            player.game_ended(game);
+        }
+    }
+    Ok(outcome)
+}
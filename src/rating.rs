@@ -0,0 +1,268 @@
+//! Elo ratings of named players, kept in a text file with one table per ruleset.
+//!
+//! Games with more than two players count as a game between every pair of players: the winner
+//! beat everybody, the others drew among each other and players that forfeited lost to all that
+//! did not.
+
+use std::{cmp::Ordering, collections::BTreeMap, fs, io::ErrorKind};
+
+use crate::{
+    notation::{format_cells, format_width, parse_cell, parse_width},
+    BoardBackend, Game, Res, Topology, TurnOutcome,
+};
+
+/// Rating of a player before their first game.
+pub const INITIAL_RATING: f64 = 1500.0;
+/// How far a single game can move a rating.
+const K: f64 = 32.0;
+
+/// The rules ratings are kept separately for.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Ruleset {
+    /// Width of the board, of a layer for a 3D board.
+    pub width: usize,
+    /// Height of the board.
+    pub height: usize,
+    /// 1 unless the board is three dimensional.
+    pub depth: usize,
+    /// Pieces in a row needed to win.
+    pub connect_size: usize,
+    /// Number of players.
+    pub player_count: u32,
+    /// Whether pieces may be popped out.
+    pub pop_out: bool,
+    /// Whether pieces fall down their column.
+    pub gravity: bool,
+    /// How lines wrap around the edges.
+    pub topology: Topology,
+    /// The shape of the board, see `Game::with_blocked_cells`.
+    pub blocked: Vec<(usize, usize)>,
+}
+impl Ruleset {
+    /// The rules `game` is played with.
+    pub fn of<B: BoardBackend>(game: &Game<B>) -> Self {
+        Ruleset {
+            width: game.width(),
+            height: game.board().height(),
+            depth: game.depth(),
+            connect_size: game.connect_size(),
+            player_count: game.player_count(),
+            pop_out: game.pop_out(),
+            gravity: game.gravity(),
+            topology: game.topology(),
+            blocked: game.board().blocked_cells(),
+        }
+    }
+
+    /// The variants of the rules in the words of the notation, like `popout`, `torus` or
+    /// `blocked 1,4 7,4`. Empty for the plain game.
+    pub fn variants(&self) -> Vec<String> {
+        let mut variants = Vec::new();
+        if !self.gravity {
+            variants.push("free".to_string());
+        } else if self.pop_out {
+            variants.push("popout".to_string());
+        }
+        if self.topology != Topology::Flat {
+            variants.push(self.topology.to_string());
+        }
+        if !self.blocked.is_empty() {
+            variants.push(format!("blocked {}", format_cells(&self.blocked)));
+        }
+        variants
+    }
+}
+
+/// The Elo rating of a player under one ruleset.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rating {
+    /// The Elo rating, `INITIAL_RATING` at first.
+    pub rating: f64,
+    /// Games that went into the rating.
+    pub games: u32,
+}
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: INITIAL_RATING,
+            games: 0,
+        }
+    }
+}
+
+/// The ratings of every player under every ruleset, see `load` and `record`.
+#[derive(Clone, Default, Debug)]
+pub struct Ratings {
+    tables: BTreeMap<Ruleset, BTreeMap<String, Rating>>,
+}
+impl Ratings {
+    /// Reads the ratings from `path`. A file that does not exist yet holds no ratings.
+    ///
+    /// Every line is `width height connect_size player_count [variants] rating games name`, with
+    /// the width of 3D boards and the variants written like in the notation.
+    pub fn load(path: &str) -> Res<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Ratings::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut ratings = Ratings::default();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || format!("Invalid rating in line {} of {}", i + 1, path);
+            let mut rest = line;
+            let [width, height, connect_size, player_count] = [(); 4].map(|_| next_word(&mut rest));
+            let ruleset = (|| {
+                let (width, depth) = parse_width(width)?;
+                let mut ruleset = Ruleset {
+                    width,
+                    height: height.parse().ok()?,
+                    depth,
+                    connect_size: connect_size.parse().ok()?,
+                    player_count: player_count.parse().ok()?,
+                    pop_out: false,
+                    gravity: true,
+                    topology: Topology::Flat,
+                    blocked: Vec::new(),
+                };
+                // The variants end at the rating, the only number among them
+                let mut blocked = false;
+                loop {
+                    let variant = rest.split(' ').next()?;
+                    if variant.parse::<f64>().is_ok() {
+                        break;
+                    }
+                    match variant {
+                        "popout" => ruleset.pop_out = true,
+                        "free" => ruleset.gravity = false,
+                        "blocked" => blocked = true,
+                        cell if blocked => ruleset.blocked.push(parse_cell(cell)?),
+                        topology => ruleset.topology = Topology::parse(topology)?,
+                    }
+                    next_word(&mut rest);
+                }
+                Some(ruleset)
+            })()
+            .ok_or_else(invalid)?;
+            let [rating, games] = [(); 2].map(|_| next_word(&mut rest));
+            let name = rest;
+            if name.is_empty() {
+                return Err(invalid().into());
+            }
+            let rating = Rating {
+                rating: rating.parse().map_err(|_| invalid())?,
+                games: games.parse().map_err(|_| invalid())?,
+            };
+            ratings
+                .tables
+                .entry(ruleset)
+                .or_default()
+                .insert(name.to_string(), rating);
+        }
+        Ok(ratings)
+    }
+
+    /// Writes the ratings to `path` in the format `load` reads.
+    pub fn save(&self, path: &str) -> Res<()> {
+        let mut text = String::new();
+        for (ruleset, table) in &self.tables {
+            let mut rules = format!(
+                "{} {} {} {}",
+                format_width(ruleset.width, ruleset.depth),
+                ruleset.height,
+                ruleset.connect_size,
+                ruleset.player_count
+            );
+            for variant in ruleset.variants() {
+                rules += &format!(" {}", variant);
+            }
+            for (name, rating) in table {
+                text += &format!("{} {:.1} {} {}\n", rules, rating.rating, rating.games, name);
+            }
+        }
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// The rating of `name` under `ruleset`, the initial one for a new player.
+    pub fn get(&self, ruleset: &Ruleset, name: &str) -> Rating {
+        self.tables
+            .get(ruleset)
+            .and_then(|table| table.get(name))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Updates the ratings after `game` ended, `names[seat]` played each seat. A player that
+    /// played several seats is not rated against themselves.
+    pub fn record<B: BoardBackend>(&mut self, game: &Game<B>, names: &[String]) -> Res<()> {
+        if !game.is_over() {
+            return Err("Only finished games can be rated".into());
+        }
+        if names.len() != game.player_count() as usize {
+            return Err("Every seat needs a name to rate the game".into());
+        }
+        // Lower is better
+        let rank = |seat: usize| match game.status() {
+            TurnOutcome::Win(winner) if winner as usize == seat => 0,
+            _ if game.has_forfeited(seat as u32) => 2,
+            _ => 1,
+        };
+        let ruleset = Ruleset::of(game);
+        let before: Vec<f64> = names
+            .iter()
+            .map(|name| self.get(&ruleset, name).rating)
+            .collect();
+
+        let table = self.tables.entry(ruleset).or_default();
+        for (seat, name) in names.iter().enumerate() {
+            let opponents: Vec<usize> = (0..names.len())
+                .filter(|&other| names[other] != *name)
+                .collect();
+            let change: f64 = opponents
+                .iter()
+                .map(|&other| {
+                    let expected = 1.0 / (1.0 + 10f64.powf((before[other] - before[seat]) / 400.0));
+                    let score = match rank(seat).cmp(&rank(other)) {
+                        Ordering::Less => 1.0,
+                        Ordering::Equal => 0.5,
+                        Ordering::Greater => 0.0,
+                    };
+                    score - expected
+                })
+                .sum();
+            let rating = table.entry(name.clone()).or_default();
+            if !opponents.is_empty() {
+                rating.rating += K * change / opponents.len() as f64;
+            }
+            if !names[..seat].contains(name) {
+                rating.games += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every ruleset with its players, best first.
+    pub fn leaderboards(&self) -> Vec<(Ruleset, Vec<(&str, Rating)>)> {
+        self.tables
+            .iter()
+            .map(|(ruleset, table)| {
+                let mut players: Vec<(&str, Rating)> = table
+                    .iter()
+                    .map(|(name, rating)| (name.as_str(), *rating))
+                    .collect();
+                players.sort_by(|a, b| b.1.rating.total_cmp(&a.1.rating));
+                (ruleset.clone(), players)
+            })
+            .collect()
+    }
+}
+
+/// Takes the word up to the next space off the front of `rest`.
+fn next_word<'a>(rest: &mut &'a str) -> &'a str {
+    let (word, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+    *rest = tail;
+    word
+}
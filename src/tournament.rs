@@ -0,0 +1,305 @@
+//! Headless matches between computer players, to measure whether a change made one stronger.
+
+use std::{
+    fs, process,
+    time::{Duration, Instant},
+};
+
+use connect_n::{play_turn, rating::Ratings, Game, Player, Res, ScriptedPlayer, TurnOutcome};
+
+use crate::config::{parse_number, GameConfig, PlayerKind};
+
+pub const USAGE: &str = "\
+Usage: connect-n tournament --entrants <list> [OPTIONS]
+
+Plays matches between computer players and reports how each of them did.
+
+Options:
+  --entrants <list>      Comma separated players, like '--seats' but without human and remote
+  --mode <mode>          round-robin: every group of entrants plays a match (default)
+                         gauntlet: the first entrant plays every group of the others
+  --games <n>            Games per match, the starting seat moves on every game (default 2)
+  --output <file>        Write the results as CSV, or as JSON if the file ends in .json
+  --ratings <file>       Rating file updated after every game (default connect-n-ratings.txt)
+  --players, --connect, --width, --height, --depth, --popout, --no-gravity, --topology,
+  --layout, --seed
+                         The rules, like for a normal game
+  -h, --help             Print this help";
+
+#[derive(Default)]
+struct Stats {
+    games: usize,
+    wins: usize,
+    draws: usize,
+    losses: usize,
+    /// Moves of all games the entrant played, by every player.
+    game_moves: usize,
+    /// Turns of the entrant and the time it thought about them.
+    turns: usize,
+    thinking: Duration,
+}
+impl Stats {
+    fn average_game_length(&self) -> f64 {
+        self.game_moves as f64 / self.games.max(1) as f64
+    }
+
+    fn average_move_millis(&self) -> f64 {
+        self.thinking.as_secs_f64() * 1000.0 / self.turns.max(1) as f64
+    }
+}
+
+struct Tournament {
+    config: GameConfig,
+    entrants: Vec<String>,
+    kinds: Vec<PlayerKind>,
+    gauntlet: bool,
+    games: usize,
+    output: Option<String>,
+}
+impl Tournament {
+    fn from_args(args: &[String]) -> Res<Self> {
+        let mut entrants = None;
+        let mut gauntlet = false;
+        let mut games = 2;
+        let mut output = None;
+        let mut rules = Vec::new();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for '{}'", flag))
+            };
+            match flag.as_str() {
+                "--entrants" => entrants = Some(value()?.clone()),
+                "--mode" => {
+                    gauntlet = match value()?.as_str() {
+                        "round-robin" => false,
+                        "gauntlet" => true,
+                        other => return Err(format!("Unknown mode '{}'", other).into()),
+                    }
+                }
+                "--games" => games = parse_number(flag, value()?)?,
+                "--output" => output = Some(value()?.clone()),
+                "--popout" | "--no-gravity" => rules.push(flag.clone()),
+                "--players" | "--connect" | "--width" | "--height" | "--depth" | "--topology"
+                | "--layout" | "--seed" | "--ratings" => {
+                    rules.extend([flag.clone(), value()?.clone()])
+                }
+                _ => return Err(format!("Unknown argument '{}'", flag).into()),
+            }
+        }
+
+        let config = GameConfig::from_args(rules)?;
+        let entrants: Vec<String> = entrants
+            .ok_or("The tournament needs '--entrants'")?
+            .split(',')
+            .map(|entrant| entrant.trim().to_string())
+            .collect();
+        let kinds = entrants
+            .iter()
+            .map(|entrant| match PlayerKind::parse(entrant)? {
+                PlayerKind::Human | PlayerKind::Remote => {
+                    Err(format!("'{}' can not play in a tournament", entrant).into())
+                }
+                kind => Ok(kind),
+            })
+            .collect::<Res<Vec<PlayerKind>>>()?;
+        // The same names as in the ratings of normal games
+        let entrants: Vec<String> = kinds.iter().map(PlayerKind::to_string).collect();
+        if entrants.len() < config.player_count as usize {
+            return Err(format!(
+                "A {} player game needs at least {} entrants",
+                config.player_count, config.player_count
+            )
+            .into());
+        }
+        if games == 0 {
+            return Err("Every match needs at least one game".into());
+        }
+        Ok(Tournament {
+            config,
+            entrants,
+            kinds,
+            gauntlet,
+            games,
+            output,
+        })
+    }
+
+    /// The entrants of every match. In a gauntlet the first entrant is part of each.
+    fn matches(&self) -> Vec<Vec<usize>> {
+        let size = self.config.player_count as usize;
+        if self.gauntlet {
+            combinations(1..self.entrants.len(), size - 1)
+                .into_iter()
+                .map(|others| [vec![0], others].concat())
+                .collect()
+        } else {
+            combinations(0..self.entrants.len(), size)
+        }
+    }
+
+    fn names(&self, entrants: &[usize]) -> String {
+        entrants
+            .iter()
+            .map(|&i| self.entrants[i].as_str())
+            .collect::<Vec<&str>>()
+            .join(" vs ")
+    }
+
+    /// Plays one game with `seating[seat]` as the entrant of every seat. Players that fail to
+    /// come up with a valid move forfeit.
+    fn play(&mut self, seating: &[usize], seed: u64, stats: &mut [Stats]) -> Res<Game> {
+        self.config.seats = seating.iter().map(|&i| self.kinds[i].clone()).collect();
+        self.config.seed = seed;
+        let mut players: Vec<Box<dyn Player>> = self
+            .config
+            .create_players(&mut || Box::new(ScriptedPlayer::new(Vec::new())))?;
+        let mut game = self.config.new_game();
+        while !game.is_over() {
+            let seat = game.active_player();
+            let entrant = seating[seat as usize];
+            let start = Instant::now();
+            let result = play_turn(&mut game, &mut players);
+            stats[entrant].thinking += start.elapsed();
+            stats[entrant].turns += 1;
+            if let Err(e) = result {
+                eprintln!("{} forfeits: {}", self.entrants[entrant], e);
+                game.forfeit(seat)?;
+            }
+        }
+
+        for (seat, &entrant) in seating.iter().enumerate() {
+            let stats = &mut stats[entrant];
+            stats.games += 1;
+            stats.game_moves += game.history().len();
+            match game.status() {
+                TurnOutcome::Win(winner) if winner as usize == seat => stats.wins += 1,
+                TurnOutcome::Draw => stats.draws += 1,
+                _ => stats.losses += 1,
+            }
+        }
+        Ok(game)
+    }
+
+    fn to_csv(&self, stats: &[Stats]) -> String {
+        let mut csv = String::from(
+            "entrant,games,wins,draws,losses,average_game_length,average_move_millis\n",
+        );
+        for (entrant, stats) in self.entrants.iter().zip(stats) {
+            csv += &format!(
+                "\"{}\",{},{},{},{},{:.2},{:.2}\n",
+                entrant.replace('"', "\"\""),
+                stats.games,
+                stats.wins,
+                stats.draws,
+                stats.losses,
+                stats.average_game_length(),
+                stats.average_move_millis()
+            );
+        }
+        csv
+    }
+
+    fn to_json(&self, stats: &[Stats]) -> String {
+        let entries: Vec<String> = self
+            .entrants
+            .iter()
+            .zip(stats)
+            .map(|(entrant, stats)| {
+                format!(
+                    "  {{\"entrant\": \"{}\", \"games\": {}, \"wins\": {}, \"draws\": {}, \
+                     \"losses\": {}, \"average_game_length\": {:.2}, \
+                     \"average_move_millis\": {:.2}}}",
+                    entrant.replace('\\', "\\\\").replace('"', "\\\""),
+                    stats.games,
+                    stats.wins,
+                    stats.draws,
+                    stats.losses,
+                    stats.average_game_length(),
+                    stats.average_move_millis()
+                )
+            })
+            .collect();
+        format!("[\n{}\n]\n", entries.join(",\n"))
+    }
+}
+
+/// Every way to pick `size` items of `items`, in order.
+fn combinations(items: std::ops::Range<usize>, size: usize) -> Vec<Vec<usize>> {
+    if size == 0 {
+        return vec![Vec::new()];
+    }
+    items
+        .clone()
+        .flat_map(|first| {
+            combinations(first + 1..items.end, size - 1)
+                .into_iter()
+                .map(move |rest| [vec![first], rest].concat())
+        })
+        .collect()
+}
+
+pub fn tournament(args: &[String]) -> Res<()> {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let mut tournament = match Tournament::from_args(args) {
+        Ok(tournament) => tournament,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let mut stats: Vec<Stats> = tournament
+        .entrants
+        .iter()
+        .map(|_| Stats::default())
+        .collect();
+    let matches = tournament.matches();
+    let total = matches.len() * tournament.games;
+    let seed = tournament.config.seed;
+    let mut ratings = Ratings::load(&tournament.config.ratings)?;
+
+    let mut played = 0;
+    for entrants in matches {
+        for round in 0..tournament.games {
+            // Every game the entrants move one seat on, so each starts equally often
+            let seating: Vec<usize> = (0..entrants.len())
+                .map(|seat| entrants[(seat + round) % entrants.len()])
+                .collect();
+            let game = tournament.play(&seating, seed.wrapping_add(played as u64), &mut stats)?;
+            played += 1;
+            let names: Vec<String> = seating
+                .iter()
+                .map(|&i| tournament.entrants[i].clone())
+                .collect();
+            ratings.record(&game, &names)?;
+            ratings.save(&tournament.config.ratings)?;
+            let result = match game.status() {
+                TurnOutcome::Win(winner) => {
+                    format!("{} won", tournament.entrants[seating[winner as usize]])
+                }
+                _ => "draw".to_string(),
+            };
+            println!(
+                "Game {} of {}: {}, {} after {} moves",
+                played,
+                total,
+                tournament.names(&seating),
+                result,
+                game.history().len()
+            );
+        }
+    }
+
+    let csv = tournament.to_csv(&stats);
+    println!("\n{}", csv);
+    match &tournament.output {
+        Some(path) if path.ends_with(".json") => fs::write(path, tournament.to_json(&stats))?,
+        Some(path) => fs::write(path, csv)?,
+        None => {}
+    }
+    Ok(())
+}